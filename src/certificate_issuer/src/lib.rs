@@ -10,6 +10,7 @@ type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdStore = StableBTreeMap<u8, u64, Memory>;
 type CertificateStore = StableBTreeMap<u64, Certificate, Memory>;
 type StudentCertificateStore = StableBTreeMap<Principal, Vec<u64>, Memory>;
+type IssuerConfigStore = StableBTreeMap<u8, IssuerConfig, Memory>;
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct Certificate {
@@ -31,6 +32,21 @@ pub struct CertificateMetadata {
     pub course_duration: String,
     pub course_level: String,
     pub skills_acquired: Vec<String>,
+    pub final_grade: Option<CertificateGrade>,
+}
+
+// Final grade as computed by the course_management gradebook at completion.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CertificateGrade {
+    pub percent: f64,
+    pub letter: String,
+}
+
+// Canisters allowed to issue certificates, normally course_management.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct IssuerConfig {
+    pub trusted_canisters: Vec<Principal>,
+    pub updated_at: u64,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -65,6 +81,12 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
         )
     );
+
+    static ISSUER_CONFIG: RefCell<IssuerConfigStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
+        )
+    );
 }
 
 fn get_next_id() -> u64 {
@@ -86,13 +108,61 @@ fn generate_verification_hash(certificate_id: u64, student: Principal, course_id
     )
 }
 
+fn issuer_config() -> Option<IssuerConfig> {
+    ISSUER_CONFIG.with(|storage| storage.borrow().get(&0))
+}
+
+#[ic_cdk::update]
+fn set_issuer_config(trusted_canisters: Vec<Principal>) -> Result<IssuerConfig, String> {
+    let caller = ic_cdk::caller();
+
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can configure certificate issuance".to_string());
+    }
+
+    let config = IssuerConfig {
+        trusted_canisters,
+        updated_at: time(),
+    };
+
+    ISSUER_CONFIG.with(|storage| {
+        storage.borrow_mut().insert(0, config.clone());
+    });
+
+    Ok(config)
+}
+
+#[ic_cdk::query]
+fn get_issuer_config() -> Option<IssuerConfig> {
+    issuer_config()
+}
+
+fn find_student_certificate(student: Principal, course_id: u64) -> Option<Certificate> {
+    let certificate_ids = STUDENT_CERTIFICATES.with(|storage| storage.borrow().get(&student).unwrap_or_default());
+
+    CERTIFICATE_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        certificate_ids
+            .iter()
+            .filter_map(|id| storage.get(id))
+            .find(|cert| cert.course_id == course_id)
+    })
+}
+
+// Issuing is idempotent per student and course so the caller can safely
+// retry a call whose outcome it did not see.
 #[ic_cdk::update]
 fn issue_certificate(payload: IssueCertificatePayload) -> Result<Certificate, String> {
     let caller = ic_cdk::caller();
     
-    // Only the educator or course management canister can issue certificates
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot issue certificates".to_string());
+    // Only the course management canister can issue certificates
+    match issuer_config() {
+        Some(config) if config.trusted_canisters.contains(&caller) => {}
+        _ => return Err("Only trusted canisters can issue certificates".to_string()),
+    }
+
+    if let Some(certificate) = find_student_certificate(payload.student, payload.course_id) {
+        return Ok(certificate);
     }
 
     let certificate_id = get_next_id();
@@ -1,31 +1,178 @@
 use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::management_canister::main::raw_rand;
 use ic_cdk::api::time;
 use ic_cdk_macros::*;
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
 use serde::Serialize;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
 use std::cell::RefCell;
+use std::ops::Bound;
+use std::time::Duration;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdStore = StableBTreeMap<u8, u64, Memory>;
 type CourseStore = StableBTreeMap<u64, Course, Memory>;
 type EnrollmentStore = StableBTreeMap<(Principal, u64), Enrollment, Memory>;
 type ProgressStore = StableBTreeMap<(Principal, u64, u64), LessonProgress, Memory>;
+type QuestionBankStore = StableBTreeMap<(u64, u64), BankQuestion, Memory>;
+type ExamStore = StableBTreeMap<u64, Exam, Memory>;
+type ExamAttemptStore = StableBTreeMap<u64, ExamAttempt, Memory>;
+type AssignmentStore = StableBTreeMap<u64, Assignment, Memory>;
+type SubmissionStore = StableBTreeMap<u64, AssignmentSubmission, Memory>;
+type GradebookStore = StableBTreeMap<u64, GradebookConfig, Memory>;
+type LearningPathStore = StableBTreeMap<u64, LearningPath, Memory>;
+type PathEnrollmentStore = StableBTreeMap<(Principal, u64), PathEnrollment, Memory>;
+type PathCompletionStore = StableBTreeMap<(Principal, u64), PathCompletionEvent, Memory>;
+type SearchIndexStore = StableBTreeMap<String, TermPostings, Memory>;
+type TagIndexStore = StableBTreeMap<String, TagPostings, Memory>;
+type LevelIndexStore = StableBTreeMap<(u8, u64), (), Memory>;
+type ReviewStore = StableBTreeMap<(u64, Principal), Review, Memory>;
+type CohortStore = StableBTreeMap<u64, Cohort, Memory>;
+type WaitlistStore = StableBTreeMap<(u64, u64), WaitlistEntry, Memory>;
+type EnrollmentRequestStore = StableBTreeMap<(u64, Principal), EnrollmentRequest, Memory>;
+type InviteCodeStore = StableBTreeMap<String, InviteCode, Memory>;
+type ConfigStore = StableBTreeMap<u8, CanisterConfig, Memory>;
+type StaffStore = StableBTreeMap<(u64, Principal), StaffMember, Memory>;
+type StaffInvitationStore = StableBTreeMap<(u64, Principal), StaffInvitation, Memory>;
+type DropStore = StableBTreeMap<u64, DropRecord, Memory>;
+type AssetStore = StableBTreeMap<u64, Asset, Memory>;
+type BlobStore = StableBTreeMap<String, StoredBlob, Memory>;
+type BlobChunkStore = StableBTreeMap<([u8; 32], u32), Vec<u8>, Memory>;
+type UploadStore = StableBTreeMap<u64, UploadSession, Memory>;
+type UploadChunkStore = StableBTreeMap<(u64, u32), Vec<u8>, Memory>;
+type AssetQuotaStore = StableBTreeMap<Principal, u64, Memory>;
+type AssetTokenStore = StableBTreeMap<String, AssetAccessToken, Memory>;
+type CompletionDispatchStore = StableBTreeMap<(Principal, u64), CompletionDispatch, Memory>;
+type CourseStudentIndexStore = StableBTreeMap<(u64, Principal), (), Memory>;
+type EducatorCourseIndexStore = StableBTreeMap<(Principal, u64), (), Memory>;
+type NotificationStore = StableBTreeMap<(Principal, u64), Notification, Memory>;
+type DeadlineReminderStore = StableBTreeMap<(Principal, u64, u64), u64, Memory>;
+type ReminderCursorStore = StableBTreeMap<u8, (u64, Principal), Memory>;
+type AnnouncementDeliveryStore = StableBTreeMap<u64, AnnouncementDelivery, Memory>;
+type AnnouncementNotificationIndexStore = StableBTreeMap<(u64, Principal), u64, Memory>;
+type AnnouncementStore = StableBTreeMap<(u64, u64), Announcement, Memory>;
+type CourseTranslationStore = StableBTreeMap<u64, CourseTranslations, Memory>;
 
-#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const MAX_PAGE_SIZE: u32 = 100;
+const MAX_QUESTION_POINTS: u32 = 1_000;
+const MAX_EXAM_TIME_LIMIT_SECS: u64 = 24 * 60 * 60;
+const MAX_BULK_ENROLLMENT: usize = 500;
+const SEAT_OFFER_WINDOW_SECS: u64 = 48 * 60 * 60;
+const DEFAULT_REFUND_WINDOW_SECS: u64 = 14 * 24 * 60 * 60;
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * NANOS_PER_SECOND;
+const MAX_ANALYTICS_DAYS: u32 = 365;
+const MAX_LESSON_BLOCKS: usize = 50;
+const MAX_TITLE_CHARS: usize = 200;
+const MAX_MARKDOWN_CHARS: usize = 100_000;
+const MAX_CODE_CHARS: usize = 50_000;
+const MAX_ANNOUNCEMENT_CHARS: usize = 10_000;
+const MAX_URL_CHARS: usize = 2048;
+const MAX_VIDEO_SECS: u64 = 24 * 60 * 60;
+const BLOCKED_LINK_SCHEMES: &[&str] = &["javascript:", "vbscript:", "data:"];
+const READING_WORDS_PER_MINUTE: u64 = 200;
+const CODE_SECS_PER_LINE: u64 = 5;
+const ASSET_CHUNK_BYTES: u64 = 1024 * 1024;
+const MAX_UPLOAD_CHUNK_BYTES: usize = 1_900_000;
+const MAX_HTTP_BODY_BYTES: u64 = 2 * ASSET_CHUNK_BYTES;
+const DEFAULT_ASSET_QUOTA_BYTES: u64 = 500 * 1024 * 1024;
+const UPLOAD_EXPIRY_SECS: u64 = 24 * 60 * 60;
+const ASSET_TOKEN_TTL_SECS: u64 = 60 * 60;
+// Accepted content types and their size limits in bytes.
+const ASSET_TYPES: &[(&str, u64)] = &[
+    ("image/png", 5 * 1024 * 1024),
+    ("image/jpeg", 5 * 1024 * 1024),
+    ("image/gif", 5 * 1024 * 1024),
+    ("image/webp", 5 * 1024 * 1024),
+    ("application/pdf", 20 * 1024 * 1024),
+    ("video/mp4", 50 * 1024 * 1024),
+    ("video/webm", 50 * 1024 * 1024),
+];
+const COURSE_BUNDLE_VERSION: u32 = 1;
+const DEFAULT_LOCALE: &str = "en";
+const MAX_LESSON_BRANCHES: usize = 10;
+const MAX_RELATIVE_SCHEDULE_SECS: u64 = 365 * 24 * 60 * 60;
+const DEADLINE_REMINDER_LEAD_SECS: u64 = 24 * 60 * 60;
+const REMINDER_SCAN_INTERVAL_SECS: u64 = 15 * 60;
+const REMINDER_BATCH_SIZE: usize = 500;
+const ANNOUNCEMENT_BATCH_SIZE: usize = 500;
+const MAX_COMPLETION_ATTEMPTS: u32 = 8;
+const COMPLETION_RETRY_BASE_SECS: u64 = 60;
+const INVITE_CODE_LENGTH: usize = 10;
+const INVITE_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
 pub enum CourseLevel {
     Beginner,
     Intermediate,
     Advanced,
 }
 
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum EnrollmentPolicy {
+    Open,
+    ApprovalRequired,
+    InviteOnly,
+}
+
+// Lessons are built from content blocks shown in order. `Quiz` refers to an
+// exam of the same course.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum LessonContent {
+    Markdown { text: String },
+    Video { url: String, duration_secs: u64 },
+    CodeSnippet { language: String, code: String },
+    Attachment { file_name: String, url: String, size_bytes: u64 },
+    ExternalLink { url: String, title: String },
+    Quiz { exam_id: u64 },
+}
+
+// `AfterEnrollment` deadlines fall `secs` after each learner enrolled.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum LessonDeadline {
+    Absolute { due_at: u64 },
+    AfterEnrollment { secs: u64 },
+}
+
+// Locked lessons cannot be opened, completed or attempted by learners.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum LessonUnlock {
+    Absolute { unlocks_at: u64 },
+    AfterEnrollment { secs: u64 },
+}
+
+// Score conditions use the learner's best submitted attempt on `exam_id`, in
+// percent; learners who have not submitted one match neither.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum BranchCondition {
+    Always,
+    ScoreBelow { exam_id: u64, percent: u32 },
+    ScoreAtLeast { exam_id: u64, percent: u32 },
+}
+
+// Conditional edge to another lesson of the same course. A lesson's branches
+// are tried in order and, when none matches, the learner continues with the
+// next lesson by `order`.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct LessonBranch {
+    pub to_lesson_id: u64,
+    pub condition: BranchCondition,
+}
+
+// `duration_secs` is estimated from the content blocks when the lesson is
+// written.
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct Lesson {
     pub id: u64,
     pub title: String,
-    pub content: String,
-    pub duration: String,
+    pub content: Vec<LessonContent>,
+    pub duration_secs: u64,
     pub order: u32,
+    pub deadline: Option<LessonDeadline>,
+    pub unlock: Option<LessonUnlock>,
+    pub branches: Vec<LessonBranch>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -38,19 +185,59 @@ pub struct Course {
     pub level: CourseLevel,
     pub image: String,
     pub tags: Vec<String>,
-    pub duration: String,
+    pub duration_secs: u64, // sum of the lesson durations
+    pub prerequisites: Vec<u64>,
+    pub published: bool,
+    pub enrollment_policy: EnrollmentPolicy,
+    pub price: u64, // in platform tokens; 0 means free
+    pub reviews_require_completion: bool,
+    pub default_locale: String,
+    pub rating_total: u64,
+    pub rating_count: u64,
+    pub average_rating: f64,
     pub enrolled_students: u64,
     pub created_at: u64,
     pub updated_at: u64,
 }
 
+// An empty title or description, a missing lesson or empty lesson content
+// falls back to the course's default locale.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct LessonTranslation {
+    pub lesson_id: u64,
+    pub title: String,
+    pub content: Vec<LessonContent>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CourseTranslation {
+    pub course_id: u64,
+    pub locale: String,
+    pub title: String,
+    pub description: String,
+    pub lessons: Vec<LessonTranslation>,
+    pub updated_by: Principal,
+    pub updated_at: u64,
+}
+
+// Every translation of one course, ordered by locale.
+#[derive(Clone, Debug, Default, CandidType, Deserialize, Serialize)]
+pub struct CourseTranslations {
+    pub translations: Vec<CourseTranslation>,
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct Enrollment {
     pub student: Principal,
     pub course_id: u64,
     pub enrolled_at: u64,
+    pub cohort_id: Option<u64>,
+    pub purchase_id: Option<u64>,
     pub completed: bool,
     pub completion_date: Option<u64>,
+    pub final_grade: Option<FinalGrade>,
+    pub certificate_id: Option<u64>,
+    pub reward_transaction_id: Option<u64>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -60,337 +247,8536 @@ pub struct LessonProgress {
     pub lesson_id: u64,
     pub completed: bool,
     pub completed_at: Option<u64>,
+    pub late: bool,
 }
 
-#[derive(Clone, Debug, CandidType, Deserialize)]
-pub struct CreateCoursePayload {
-    pub title: String,
-    pub description: String,
-    pub lessons: Vec<CreateLessonPayload>,
-    pub level: CourseLevel,
-    pub image: String,
-    pub tags: Vec<String>,
-    pub duration: String,
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum QuestionDifficulty {
+    Easy,
+    Medium,
+    Hard,
 }
 
-#[derive(Clone, Debug, CandidType, Deserialize)]
-pub struct CreateLessonPayload {
-    pub title: String,
-    pub content: String,
-    pub duration: String,
-    pub order: u32,
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct BankQuestion {
+    pub id: u64,
+    pub course_id: u64,
+    pub topic: String,
+    pub difficulty: QuestionDifficulty,
+    pub prompt: String,
+    pub options: Vec<String>,
+    pub correct_option: u32,
+    pub points: u32,
+    pub created_at: u64,
+    pub updated_at: u64,
 }
 
-#[derive(Clone, Debug, CandidType, Deserialize)]
-pub struct UpdateCoursePayload {
-    pub title: Option<String>,
-    pub description: Option<String>,
-    pub level: Option<CourseLevel>,
-    pub image: Option<String>,
-    pub tags: Option<Vec<String>>,
-    pub duration: Option<String>,
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum ExamKind {
+    LessonQuiz { lesson_id: u64 },
+    FinalExam,
 }
 
-thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
-        MemoryManager::init(DefaultMemoryImpl::default())
-    );
+// Each section draws `question_count` questions from the bank entries
+// matching its topic and difficulty; `None` matches any value.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ExamSection {
+    pub topic: Option<String>,
+    pub difficulty: Option<QuestionDifficulty>,
+    pub question_count: u32,
+}
 
-    static ID_COUNTER: RefCell<IdStore> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0)))
-        )
-    );
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Exam {
+    pub id: u64,
+    pub course_id: u64,
+    pub title: String,
+    pub kind: ExamKind,
+    pub sections: Vec<ExamSection>,
+    pub time_limit_secs: u64,
+    pub max_attempts: u32,
+    pub passing_score: u32, // percent
+    pub created_at: u64,
+    pub updated_at: u64,
+}
 
-    static COURSE_STORAGE: RefCell<CourseStore> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
-        )
-    );
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum ExamAttemptStatus {
+    InProgress,
+    Submitted,
+    Expired,
+}
 
-    static ENROLLMENT_STORAGE: RefCell<EnrollmentStore> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
-        )
-    );
+// Snapshot of a bank question as drawn for an attempt, so grading and
+// audits are unaffected by later edits to the bank.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ExamAttemptQuestion {
+    pub question_id: u64,
+    pub topic: String,
+    pub difficulty: QuestionDifficulty,
+    pub prompt: String,
+    pub options: Vec<String>,
+    pub correct_option: u32,
+    pub points: u32,
+    pub selected_option: Option<u32>,
+}
 
-    static PROGRESS_STORAGE: RefCell<ProgressStore> = RefCell::new(
-        StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
-        )
-    );
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ExamAttempt {
+    pub id: u64,
+    pub exam_id: u64,
+    pub course_id: u64,
+    pub student: Principal,
+    pub attempt_number: u32,
+    pub seed: Vec<u8>,
+    pub questions: Vec<ExamAttemptQuestion>,
+    pub started_at: u64,
+    pub deadline: u64,
+    pub submitted_at: Option<u64>,
+    pub status: ExamAttemptStatus,
+    pub score: u32,
+    pub max_score: u32,
+    pub passed: bool,
 }
 
-fn get_next_id() -> u64 {
-    ID_COUNTER.with(|counter| {
-        let current_id = counter.borrow().get(&0).unwrap_or(0);
-        let next_id = current_id + 1;
-        counter.borrow_mut().insert(0, next_id);
-        next_id
-    })
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ExamPaperQuestion {
+    pub question_id: u64,
+    pub prompt: String,
+    pub options: Vec<String>,
+    pub points: u32,
 }
 
-#[ic_cdk::update]
-fn create_course(payload: CreateCoursePayload) -> Result<Course, String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot create courses".to_string());
-    }
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ExamPaper {
+    pub attempt_id: u64,
+    pub exam_id: u64,
+    pub title: String,
+    pub questions: Vec<ExamPaperQuestion>,
+    pub started_at: u64,
+    pub deadline: u64,
+}
 
-    let course_id = get_next_id();
-    let lessons: Vec<Lesson> = payload
-        .lessons
-        .into_iter()
-        .enumerate()
-        .map(|(index, lesson_payload)| Lesson {
-            id: (index + 1) as u64,
-            title: lesson_payload.title,
-            content: lesson_payload.content,
-            duration: lesson_payload.duration,
-            order: lesson_payload.order,
-        })
-        .collect();
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ExamResult {
+    pub attempt_id: u64,
+    pub exam_id: u64,
+    pub attempt_number: u32,
+    pub status: ExamAttemptStatus,
+    pub score: u32,
+    pub max_score: u32,
+    pub passed: bool,
+    pub started_at: u64,
+    pub submitted_at: Option<u64>,
+}
 
-    let course = Course {
-        id: course_id,
-        title: payload.title,
-        description: payload.description,
-        educator: caller,
-        lessons,
-        level: payload.level,
-        image: payload.image,
-        tags: payload.tags,
-        duration: payload.duration,
-        enrolled_students: 0,
-        created_at: time(),
-        updated_at: time(),
-    };
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct RubricCriterion {
+    pub name: String,
+    pub description: String,
+    pub max_points: u32,
+}
 
-    COURSE_STORAGE.with(|storage| {
-        storage.borrow_mut().insert(course_id, course.clone());
-        Ok(course)
-    })
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Assignment {
+    pub id: u64,
+    pub course_id: u64,
+    pub lesson_id: u64,
+    pub title: String,
+    pub instructions: String,
+    pub rubric: Vec<RubricCriterion>,
+    pub passing_points: u32,
+    pub allow_resubmission: bool,
+    pub max_submissions: u32,
+    pub required_for_completion: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
 }
 
-#[ic_cdk::query]
-fn get_course(course_id: u64) -> Option<Course> {
-    COURSE_STORAGE.with(|storage| storage.borrow().get(&course_id))
+// `criterion` is the index of the scored entry in the assignment rubric.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CriterionScore {
+    pub criterion: u32,
+    pub points: u32,
+    pub comment: String,
 }
 
-#[ic_cdk::query]
-fn get_all_courses() -> Vec<Course> {
-    COURSE_STORAGE.with(|storage| {
-        storage.borrow().iter().map(|(_, course)| course).collect()
-    })
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct AssignmentGrade {
+    pub grader: Principal,
+    pub scores: Vec<CriterionScore>,
+    pub total_points: u32,
+    pub max_points: u32,
+    pub passed: bool,
+    pub feedback: String,
+    pub graded_at: u64,
 }
 
-#[ic_cdk::query]
-fn get_courses_by_educator(educator: Principal) -> Vec<Course> {
-    COURSE_STORAGE.with(|storage| {
-        storage
-            .borrow()
-            .iter()
-            .filter_map(|(_, course)| {
-                if course.educator == educator {
-                    Some(course)
-                } else {
-                    None
-                }
-            })
-            .collect()
-    })
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct AssignmentSubmission {
+    pub id: u64,
+    pub assignment_id: u64,
+    pub course_id: u64,
+    pub student: Principal,
+    pub submission_number: u32,
+    pub text: String,
+    pub file_references: Vec<String>,
+    pub submitted_at: u64,
+    pub grade: Option<AssignmentGrade>,
 }
 
-#[ic_cdk::update]
-fn update_course(course_id: u64, payload: UpdateCoursePayload) -> Result<Course, String> {
-    let caller = ic_cdk::caller();
-    
-    COURSE_STORAGE.with(|storage| {
-        let mut storage = storage.borrow_mut();
-        match storage.get(&course_id) {
-            Some(mut course) => {
-                if course.educator != caller {
-                    return Err("Only the course educator can update this course".to_string());
-                }
+// Component weights in percent; they must add up to 100.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct GradeWeights {
+    pub quizzes: u32,
+    pub exams: u32,
+    pub assignments: u32,
+}
 
-                if let Some(title) = payload.title {
-                    course.title = title;
-                }
-                if let Some(description) = payload.description {
-                    course.description = description;
-                }
-                if let Some(level) = payload.level {
-                    course.level = level;
-                }
-                if let Some(image) = payload.image {
-                    course.image = image;
-                }
-                if let Some(tags) = payload.tags {
-                    course.tags = tags;
-                }
-                if let Some(duration) = payload.duration {
-                    course.duration = duration;
-                }
-                course.updated_at = time();
-                
-                storage.insert(course_id, course.clone());
-                Ok(course)
-            }
-            None => Err("Course not found".to_string()),
-        }
-    })
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct GradeBand {
+    pub letter: String,
+    pub min_percent: u32,
 }
 
-#[ic_cdk::update]
-fn enroll_in_course(course_id: u64) -> Result<Enrollment, String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot enroll in courses".to_string());
-    }
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct GradebookConfig {
+    pub course_id: u64,
+    pub weights: GradeWeights,
+    pub bands: Vec<GradeBand>,
+    pub passing_percent: u32,
+    pub require_passing_grade: bool,
+    pub updated_at: u64,
+}
 
-    // Check if course exists
-    let course_exists = COURSE_STORAGE.with(|storage| {
-        storage.borrow().contains_key(&course_id)
-    });
+// Component percentages are `None` when the course has nothing of that kind;
+// the remaining weights are then scaled up to cover the final grade.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct FinalGrade {
+    pub student: Principal,
+    pub course_id: u64,
+    pub quiz_percent: Option<f64>,
+    pub exam_percent: Option<f64>,
+    pub assignment_percent: Option<f64>,
+    pub final_percent: f64,
+    pub letter: String,
+    pub passed: bool,
+    pub computed_at: u64,
+}
 
-    if !course_exists {
-        return Err("Course not found".to_string());
-    }
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PathStep {
+    pub course_id: u64,
+    pub elective: bool,
+}
 
-    // Check if already enrolled
-    let enrollment_key = (caller, course_id);
-    let already_enrolled = ENROLLMENT_STORAGE.with(|storage| {
-        storage.borrow().contains_key(&enrollment_key)
-    });
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct LearningPath {
+    pub id: u64,
+    pub title: String,
+    pub description: String,
+    pub creator: Principal,
+    pub steps: Vec<PathStep>,
+    pub required_electives: u32,
+    pub completion_bonus: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
 
-    if already_enrolled {
-        return Err("Already enrolled in this course".to_string());
-    }
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PathEnrollment {
+    pub student: Principal,
+    pub path_id: u64,
+    pub enrolled_at: u64,
+    pub completed: bool,
+    pub completion_date: Option<u64>,
+}
 
-    let enrollment = Enrollment {
+// Recorded when a learner finishes a path. The path certificate and bonus go
+// through the completion dispatch queue and are filled in once issued.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PathCompletionEvent {
+    pub id: u64,
+    pub path_id: u64,
+    pub student: Principal,
+    pub completed_at: u64,
+    pub completion_bonus: u64,
+    pub certificate_id: Option<u64>,
+    pub bonus_transaction_id: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PathStepProgress {
+    pub course_id: u64,
+    pub title: String,
+    pub elective: bool,
+    pub enrolled: bool,
+    pub completed: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PathProgress {
+    pub path_id: u64,
+    pub title: String,
+    pub steps: Vec<PathStepProgress>,
+    pub required_completed: u32,
+    pub required_total: u32,
+    pub electives_completed: u32,
+    pub electives_required: u32,
+    pub percent_complete: f64,
+    pub next_course_id: Option<u64>,
+    pub completed: bool,
+}
+
+// Search index entry: every course containing a term, with the term's weight
+// in that course.
+#[derive(Clone, Debug, Default, CandidType, Deserialize, Serialize)]
+pub struct TermPostings {
+    pub postings: Vec<(u64, u32)>,
+}
+
+// Published courses carrying a normalized tag.
+#[derive(Clone, Debug, Default, CandidType, Deserialize, Serialize)]
+pub struct TagPostings {
+    pub course_ids: Vec<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SearchHit {
+    pub course: Course,
+    pub score: f64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SearchResults {
+    pub total: u64,
+    pub hits: Vec<SearchHit>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ReviewResponse {
+    pub responder: Principal,
+    pub text: String,
+    pub responded_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Review {
+    pub course_id: u64,
+    pub reviewer: Principal,
+    pub rating: u8,
+    pub text: String,
+    pub response: Option<ReviewResponse>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ReviewPage {
+    pub total: u64,
+    pub reviews: Vec<Review>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct EnrollmentPage {
+    pub total: u64,
+    pub enrollments: Vec<Enrollment>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LessonProgressPage {
+    pub total: u64,
+    pub progress: Vec<LessonProgress>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum NotificationKind {
+    DeadlineReminder { lesson_id: u64, due_at: u64 },
+    Announcement { announcement_id: u64 },
+    SeatOffered { cohort_id: u64, expires_at: u64 },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Notification {
+    pub id: u64,
+    pub recipient: Principal,
+    pub course_id: u64,
+    pub kind: NotificationKind,
+    pub created_at: u64,
+    pub read_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Announcement {
+    pub id: u64,
+    pub course_id: u64,
+    pub author: Principal,
+    pub title: String,
+    pub body: String,
+    pub pinned: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AnnouncementPayload {
+    pub title: String,
+    pub body: String,
+    pub pinned: bool,
+}
+
+// `read_at` is the caller's own read time; staff listings leave it empty.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AnnouncementView {
+    pub announcement: Announcement,
+    pub read_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AnnouncementPage {
+    pub total: u64,
+    pub announcements: Vec<AnnouncementView>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AnnouncementReadStats {
+    pub announcement_id: u64,
+    pub recipients: u64,
+    pub read: u64,
+}
+
+// Learners still to be notified come after `last_student` in
+// COURSE_STUDENT_INDEX order.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct AnnouncementDelivery {
+    pub course_id: u64,
+    pub last_student: Option<Principal>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UnlockCalendarEntry {
+    pub course_id: u64,
+    pub course_title: String,
+    pub lesson_id: u64,
+    pub lesson_title: String,
+    pub order: u32,
+    pub unlocks_at: u64,
+    pub unlocked: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpcomingDeadline {
+    pub course_id: u64,
+    pub course_title: String,
+    pub lesson_id: u64,
+    pub lesson_title: String,
+    pub due_at: u64,
+    pub overdue: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum StaffRole {
+    Owner,
+    CoInstructor,
+    TeachingAssistant,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum StaffPermission {
+    EditContent,
+    Grade,
+    ManageEnrollments,
+    ViewAnalytics,
+}
+
+// The course educator is the owner and holds every permission; other staff
+// only hold the permissions listed on their record.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct StaffMember {
+    pub course_id: u64,
+    pub member: Principal,
+    pub role: StaffRole,
+    pub permissions: Vec<StaffPermission>,
+    pub added_by: Principal,
+    pub added_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct StaffInvitation {
+    pub course_id: u64,
+    pub invitee: Principal,
+    pub role: StaffRole,
+    pub permissions: Vec<StaffPermission>,
+    pub invited_by: Principal,
+    pub invited_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq)]
+pub enum AssetAccess {
+    Public,
+    EnrolledOnly,
+}
+
+// Served at `/assets/<id>`. Identical uploads share one stored blob, keyed
+// by the hex SHA-256 of the content.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Asset {
+    pub id: u64,
+    pub course_id: u64,
+    pub owner: Principal,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+    pub access: AssetAccess,
+    pub created_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct StoredBlob {
+    pub size_bytes: u64,
+    pub chunk_count: u32,
+    pub ref_count: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct UploadSession {
+    pub id: u64,
+    pub course_id: u64,
+    pub owner: Principal,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    pub access: AssetAccess,
+    pub received_bytes: u64,
+    pub started_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AssetUsage {
+    pub used_bytes: u64,
+    pub reserved_bytes: u64, // declared size of unfinished uploads
+    pub quota_bytes: u64,
+}
+
+// Grants a principal short-lived HTTP access to an enrolled-only asset by
+// appending `?token=<token>` to its URL.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct AssetAccessToken {
+    pub token: String,
+    pub asset_id: u64,
+    pub holder: Principal,
+    pub expires_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: ByteBuf,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: ByteBuf,
+    pub streaming_strategy: Option<StreamingStrategy>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct StreamingToken {
+    pub asset_id: u64,
+    pub chunk_index: u32,
+    pub access_token: Option<String>,
+}
+
+candid::define_function!(pub StreamingCallback : (StreamingToken) -> (StreamingCallbackHttpResponse) query);
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum StreamingStrategy {
+    Callback {
+        callback: StreamingCallback,
+        token: StreamingToken,
+    },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct StreamingCallbackHttpResponse {
+    pub body: ByteBuf,
+    pub token: Option<StreamingToken>,
+}
+
+// Snapshot of where a learner stood when they left a course, kept for
+// drop-off analytics.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct DropRecord {
+    pub id: u64,
+    pub student: Principal,
+    pub course_id: u64,
+    pub cohort_id: Option<u64>,
+    pub reason: Option<String>,
+    pub lessons_completed: u32,
+    pub last_completed_lesson: Option<u64>,
+    pub kept_progress: bool,
+    pub refunded_purchase: Option<u64>,
+    pub refund_error: Option<String>,
+    pub enrolled_at: u64,
+    pub dropped_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CohortDeadline {
+    pub lesson_id: u64,
+    pub due_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Cohort {
+    pub id: u64,
+    pub course_id: u64,
+    pub name: String,
+    pub starts_at: u64,
+    pub ends_at: u64,
+    pub enrollment_opens_at: u64,
+    pub enrollment_closes_at: u64,
+    pub max_seats: u32,
+    pub enrolled_count: u32,
+    pub deadlines: Vec<CohortDeadline>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// `seat_offered_at` is set once a seat in a paid cohort is held for the
+// learner; see `offer_waitlist_seats`.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct WaitlistEntry {
+    pub cohort_id: u64,
+    pub student: Principal,
+    pub joined_at: u64,
+    pub seat_offered_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum CohortEnrollmentStatus {
+    Enrolled(Box<Enrollment>),
+    Waitlisted { position: u32 },
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct EnrollmentRequest {
+    pub course_id: u64,
+    pub student: Principal,
+    pub cohort_id: Option<u64>,
+    pub message: String,
+    pub requested_at: u64,
+}
+
+// `max_uses` and `expires_at` are unlimited when `None`.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct InviteCode {
+    pub code: String,
+    pub course_id: u64,
+    pub cohort_id: Option<u64>,
+    pub created_by: Principal,
+    pub max_uses: Option<u32>,
+    pub uses: u32,
+    pub expires_at: Option<u64>,
+    pub revoked: bool,
+    pub created_at: u64,
+}
+
+// `token_rewards_canister` must be set, and must list this canister as
+// trusted, before paid courses can be enrolled in. Completion certificates
+// and rewards are queued until both canisters are configured.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CanisterConfig {
+    pub token_rewards_canister: Option<Principal>,
+    pub certificate_issuer_canister: Option<Principal>,
+    pub refund_window_secs: u64,
+    pub updated_at: u64,
+}
+
+// Mirrors of the token_rewards purchase types.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PurchasePayload {
+    pub buyer: Principal,
+    pub seller: Principal,
+    pub course_id: u64,
+    pub price: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct Purchase {
+    pub id: u64,
+    pub merchant: Principal,
+    pub buyer: Principal,
+    pub seller: Principal,
+    pub course_id: u64,
+    pub price: u64,
+    pub platform_fee: u64,
+    pub treasury: Principal,
+    pub purchased_at: u64,
+    pub refunded_at: Option<u64>,
+}
+
+// Mirrors of the certificate_issuer types.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CertificateGrade {
+    pub percent: f64,
+    pub letter: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CertificateMetadata {
+    pub completion_date: u64,
+    pub total_lessons: u32,
+    pub course_duration: String,
+    pub course_level: String,
+    pub skills_acquired: Vec<String>,
+    pub final_grade: Option<CertificateGrade>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct IssueCertificatePayload {
+    pub student: Principal,
+    pub course_id: u64,
+    pub course_title: String,
+    pub educator: Principal,
+    pub educator_name: String,
+    pub metadata: CertificateMetadata,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct Certificate {
+    pub id: u64,
+    pub verification_hash: String,
+}
+
+// The subset of a token_rewards transaction this canister keeps.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct RewardTransaction {
+    pub id: u64,
+    pub amount: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub enum CompletionKind {
+    Course,
+    LearningPath,
+}
+
+// Certificate and reward calls still owed for a completed course or learning
+// path. `target_id` is the course or path id; both come from the same id
+// counter, so they never collide. Failed calls are retried on a timer with
+// exponential backoff until MAX_COMPLETION_ATTEMPTS is reached; after that
+// only a manual retry helps.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct CompletionDispatch {
+    pub student: Principal,
+    pub kind: CompletionKind,
+    pub target_id: u64,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<u64>,
+    pub created_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct BulkEnrollmentFailure {
+    pub student: Principal,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct BulkEnrollmentResult {
+    pub enrolled: Vec<Enrollment>,
+    pub failed: Vec<BulkEnrollmentFailure>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LessonFunnelStep {
+    pub lesson_id: u64,
+    pub title: String,
+    pub order: u32,
+    pub completed_count: u32,
+    pub percent_of_enrolled: f64,
+}
+
+// Learners who stopped after completing `last_completed_lesson` (`None` if
+// they never completed a lesson), split by whether they dropped the course
+// or are still enrolled without finishing.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct DropOffPoint {
+    pub last_completed_lesson: Option<u64>,
+    pub dropped: u32,
+    pub stalled: u32,
+}
+
+// `day_start` is the UTC midnight, in nanoseconds, of the counted day.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct DailyEnrollmentCount {
+    pub day_start: u64,
+    pub enrollments: u32,
+}
+
+// Completion rate is over every learner who ever enrolled, including drops.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CourseAnalytics {
+    pub course_id: u64,
+    pub active_enrollments: u32,
+    pub completed_enrollments: u32,
+    pub dropped_enrollments: u32,
+    pub completion_rate: f64,
+    pub median_completion_secs: Option<u64>,
+    pub lesson_funnel: Vec<LessonFunnelStep>,
+    pub drop_off: Vec<DropOffPoint>,
+    pub daily_enrollments: Vec<DailyEnrollmentCount>,
+    pub generated_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct NextLesson {
+    pub lesson_id: u64,
+    pub title: String,
+    pub order: u32,
+}
+
+// `revisit` is set when the graph leads back to a completed lesson, e.g. to
+// retake its quiz after remedial work.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LessonRecommendation {
+    pub lesson_id: u64,
+    pub title: String,
+    pub order: u32,
+    pub via_branch: bool,
+    pub revisit: bool,
+    pub locked_until: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CourseProgressSummary {
+    pub course_id: u64,
+    pub course_title: String,
+    pub enrolled_at: u64,
+    pub completed: bool,
+    pub total_lessons: u32,
+    pub completed_lessons: u32,
+    pub percent_complete: f64,
+    pub next_lesson: Option<NextLesson>,
+    pub last_activity_at: u64,
+    pub estimated_remaining_secs: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LessonProgressDetail {
+    pub lesson_id: u64,
+    pub title: String,
+    pub order: u32,
+    pub duration_secs: u64,
+    pub completed: bool,
+    pub completed_at: Option<u64>,
+    pub due_at: Option<u64>,
+    pub late: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CourseProgressDetail {
+    pub summary: CourseProgressSummary,
+    pub lessons: Vec<LessonProgressDetail>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct TagFacetCount {
+    pub tag: String,
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LevelFacetCount {
+    pub level: CourseLevel,
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CatalogFacets {
+    pub total_published: u64,
+    pub tags: Vec<TagFacetCount>,
+    pub levels: Vec<LevelFacetCount>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CoursePage {
+    pub total: u64,
+    pub courses: Vec<Course>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PrerequisiteNode {
+    pub course_id: u64,
+    pub title: String,
+    pub enrolled: bool,
+    pub completed: bool,
+    pub prerequisites: Vec<PrerequisiteNode>,
+}
+
+// Portable, versioned copy of a course for moving it between deployments or
+// authoring it offline. Entries refer to each other by the ids they had where
+// the bundle was exported; `import_course` assigns fresh ids. Assets are
+// referenced by content hash and their bytes are not included.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CourseBundle {
+    pub format_version: u32,
+    pub exported_at: u64,
+    pub manifest: CourseManifest,
+    pub lessons: Vec<Lesson>,
+    pub questions: Vec<BankQuestionPayload>,
+    pub exams: Vec<BundleExam>,
+    pub assets: Vec<BundleAsset>,
+}
+
+// Prerequisites are left out since course ids only mean something within
+// one deployment.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CourseManifest {
+    pub source_course_id: u64,
+    pub title: String,
+    pub description: String,
+    pub level: CourseLevel,
+    pub image: String,
+    pub tags: Vec<String>,
+    pub enrollment_policy: EnrollmentPolicy,
+    pub price: u64,
+    pub reviews_require_completion: bool,
+    pub default_locale: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct BundleExam {
+    pub id: u64,
+    pub title: String,
+    pub kind: ExamKind,
+    pub sections: Vec<ExamSection>,
+    pub time_limit_secs: u64,
+    pub max_attempts: u32,
+    pub passing_score: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct BundleAsset {
+    pub id: u64,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+    pub access: AssetAccess,
+}
+
+// `path` points at the offending entry, e.g. `lessons[2].content[0]`.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct BundleIssue {
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct BundleIdMapping {
+    pub source_id: u64,
+    pub id: u64,
+}
+
+// Nothing is written when `issues` is non-empty or on a dry run.
+// `missing_assets` lists bundle asset ids whose content the importer cannot
+// reach in this canister and has to upload again.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CourseImportReport {
+    pub dry_run: bool,
+    pub issues: Vec<BundleIssue>,
+    pub course: Option<Course>,
+    pub exam_ids: Vec<BundleIdMapping>,
+    pub asset_ids: Vec<BundleIdMapping>,
+    pub missing_assets: Vec<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateCoursePayload {
+    pub title: String,
+    pub description: String,
+    pub lessons: Vec<CreateLessonPayload>,
+    pub level: CourseLevel,
+    pub image: String,
+    pub tags: Vec<String>,
+    pub prerequisites: Vec<u64>,
+    pub published: bool,
+    pub enrollment_policy: EnrollmentPolicy,
+    pub price: u64,
+    pub default_locale: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateLessonPayload {
+    pub title: String,
+    pub content: Vec<LessonContent>,
+    pub order: u32,
+    pub deadline: Option<LessonDeadline>,
+    pub unlock: Option<LessonUnlock>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpdateLessonPayload {
+    pub title: Option<String>,
+    pub content: Option<Vec<LessonContent>>,
+    pub order: Option<u32>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpdateCoursePayload {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub level: Option<CourseLevel>,
+    pub image: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub prerequisites: Option<Vec<u64>>,
+    pub published: Option<bool>,
+    pub enrollment_policy: Option<EnrollmentPolicy>,
+    pub price: Option<u64>,
+    pub reviews_require_completion: Option<bool>,
+    pub default_locale: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CourseTranslationPayload {
+    pub locale: String,
+    pub title: String,
+    pub description: String,
+    pub lessons: Vec<LessonTranslation>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct BankQuestionPayload {
+    pub topic: String,
+    pub difficulty: QuestionDifficulty,
+    pub prompt: String,
+    pub options: Vec<String>,
+    pub correct_option: u32,
+    pub points: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateExamPayload {
+    pub course_id: u64,
+    pub title: String,
+    pub kind: ExamKind,
+    pub sections: Vec<ExamSection>,
+    pub time_limit_secs: u64,
+    pub max_attempts: u32,
+    pub passing_score: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ExamAnswer {
+    pub question_id: u64,
+    pub selected_option: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateAssignmentPayload {
+    pub course_id: u64,
+    pub lesson_id: u64,
+    pub title: String,
+    pub instructions: String,
+    pub rubric: Vec<RubricCriterion>,
+    pub passing_points: u32,
+    pub allow_resubmission: bool,
+    pub max_submissions: u32,
+    pub required_for_completion: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpdateAssignmentPayload {
+    pub title: Option<String>,
+    pub instructions: Option<String>,
+    pub rubric: Option<Vec<RubricCriterion>>,
+    pub passing_points: Option<u32>,
+    pub allow_resubmission: Option<bool>,
+    pub max_submissions: Option<u32>,
+    pub required_for_completion: Option<bool>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SubmitAssignmentPayload {
+    pub text: String,
+    pub file_references: Vec<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct GradeSubmissionPayload {
+    pub scores: Vec<CriterionScore>,
+    pub feedback: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateLearningPathPayload {
+    pub title: String,
+    pub description: String,
+    pub steps: Vec<PathStep>,
+    pub required_electives: u32,
+    pub completion_bonus: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpdateLearningPathPayload {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub steps: Option<Vec<PathStep>>,
+    pub required_electives: Option<u32>,
+    pub completion_bonus: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SearchQuery {
+    pub text: String,
+    pub level: Option<CourseLevel>,
+    pub tags: Vec<String>,
+    pub offset: u32,
+    pub limit: u32,
+    pub locale: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateCohortPayload {
+    pub course_id: u64,
+    pub name: String,
+    pub starts_at: u64,
+    pub ends_at: u64,
+    pub enrollment_opens_at: u64,
+    pub enrollment_closes_at: u64,
+    pub max_seats: u32,
+    pub deadlines: Vec<CohortDeadline>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UpdateCohortPayload {
+    pub name: Option<String>,
+    pub starts_at: Option<u64>,
+    pub ends_at: Option<u64>,
+    pub enrollment_opens_at: Option<u64>,
+    pub enrollment_closes_at: Option<u64>,
+    pub max_seats: Option<u32>,
+    pub deadlines: Option<Vec<CohortDeadline>>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateInviteCodePayload {
+    pub course_id: u64,
+    pub cohort_id: Option<u64>,
+    pub max_uses: Option<u32>,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CanisterConfigPayload {
+    pub token_rewards_canister: Option<Principal>,
+    pub certificate_issuer_canister: Option<Principal>,
+    pub refund_window_secs: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UnenrollPayload {
+    pub reason: Option<String>,
+    pub keep_progress: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct BeginAssetUploadPayload {
+    pub course_id: u64,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: u64,
+    pub access: AssetAccess,
+}
+
+// `permissions` defaults to the role's standard set when `None`.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct StaffPayload {
+    pub role: StaffRole,
+    pub permissions: Option<Vec<StaffPermission>>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ReviewPayload {
+    pub rating: u8,
+    pub text: String,
+}
+
+// Tags are combined with AND, levels with OR; empty lists match everything.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CatalogFilter {
+    pub tags: Vec<String>,
+    pub levels: Vec<CourseLevel>,
+    pub offset: u32,
+    pub limit: u32,
+    pub locale: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct GradebookConfigPayload {
+    pub weights: GradeWeights,
+    pub bands: Vec<GradeBand>,
+    pub passing_percent: u32,
+    pub require_passing_grade: bool,
+}
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+
+    static ID_COUNTER: RefCell<IdStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0)))
+        )
+    );
+
+    static COURSE_STORAGE: RefCell<CourseStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
+        )
+    );
+
+    static ENROLLMENT_STORAGE: RefCell<EnrollmentStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
+        )
+    );
+
+    static PROGRESS_STORAGE: RefCell<ProgressStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
+        )
+    );
+
+    static QUESTION_BANK_STORAGE: RefCell<QuestionBankStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+        )
+    );
+
+    static EXAM_STORAGE: RefCell<ExamStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+        )
+    );
+
+    static EXAM_ATTEMPT_STORAGE: RefCell<ExamAttemptStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
+        )
+    );
+
+    static ASSIGNMENT_STORAGE: RefCell<AssignmentStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+        )
+    );
+
+    static SUBMISSION_STORAGE: RefCell<SubmissionStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8)))
+        )
+    );
+
+    static GRADEBOOK_STORAGE: RefCell<GradebookStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9)))
+        )
+    );
+
+    static LEARNING_PATH_STORAGE: RefCell<LearningPathStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10)))
+        )
+    );
+
+    static PATH_ENROLLMENT_STORAGE: RefCell<PathEnrollmentStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11)))
+        )
+    );
+
+    static PATH_COMPLETION_STORAGE: RefCell<PathCompletionStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12)))
+        )
+    );
+
+    static SEARCH_INDEX: RefCell<SearchIndexStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13)))
+        )
+    );
+
+    static TAG_INDEX: RefCell<TagIndexStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14)))
+        )
+    );
+
+    static LEVEL_INDEX: RefCell<LevelIndexStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15)))
+        )
+    );
+
+    static REVIEW_STORAGE: RefCell<ReviewStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16)))
+        )
+    );
+
+    static COHORT_STORAGE: RefCell<CohortStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17)))
+        )
+    );
+
+    static WAITLIST_STORAGE: RefCell<WaitlistStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18)))
+        )
+    );
+
+    static ENROLLMENT_REQUEST_STORAGE: RefCell<EnrollmentRequestStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19)))
+        )
+    );
+
+    static INVITE_CODE_STORAGE: RefCell<InviteCodeStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20)))
+        )
+    );
+
+    static CONFIG_STORAGE: RefCell<ConfigStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21)))
+        )
+    );
+
+    static STAFF_STORAGE: RefCell<StaffStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22)))
+        )
+    );
+
+    static STAFF_INVITATION_STORAGE: RefCell<StaffInvitationStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23)))
+        )
+    );
+
+    static DROP_STORAGE: RefCell<DropStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24)))
+        )
+    );
+
+    static ASSET_STORAGE: RefCell<AssetStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25)))
+        )
+    );
+
+    static BLOB_STORAGE: RefCell<BlobStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26)))
+        )
+    );
+
+    static BLOB_CHUNK_STORAGE: RefCell<BlobChunkStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(27)))
+        )
+    );
+
+    static UPLOAD_STORAGE: RefCell<UploadStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(28)))
+        )
+    );
+
+    static UPLOAD_CHUNK_STORAGE: RefCell<UploadChunkStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(29)))
+        )
+    );
+
+    static ASSET_QUOTA_STORAGE: RefCell<AssetQuotaStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(30)))
+        )
+    );
+
+    static ASSET_TOKEN_STORAGE: RefCell<AssetTokenStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(31)))
+        )
+    );
+
+    static COMPLETION_DISPATCH_STORAGE: RefCell<CompletionDispatchStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(32)))
+        )
+    );
+
+    static COURSE_STUDENT_INDEX: RefCell<CourseStudentIndexStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(33)))
+        )
+    );
+
+    static EDUCATOR_COURSE_INDEX: RefCell<EducatorCourseIndexStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(34)))
+        )
+    );
+
+    static NOTIFICATION_STORAGE: RefCell<NotificationStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(35)))
+        )
+    );
+
+    // Due time each (student, course, lesson) was last reminded about.
+    static DEADLINE_REMINDER_STORAGE: RefCell<DeadlineReminderStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(36)))
+        )
+    );
+
+    static ANNOUNCEMENT_STORAGE: RefCell<AnnouncementStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(37)))
+        )
+    );
+
+    static COURSE_TRANSLATION_STORAGE: RefCell<CourseTranslationStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(38)))
+        )
+    );
+
+    // Last (course, student) entry of COURSE_STUDENT_INDEX the reminder scan
+    // handled; absent between scans.
+    static REMINDER_CURSOR: RefCell<ReminderCursorStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(39)))
+        )
+    );
+
+    // Announcements whose notifications are still being sent, by id.
+    static ANNOUNCEMENT_DELIVERY_STORAGE: RefCell<AnnouncementDeliveryStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(40)))
+        )
+    );
+
+    // Notification id of each learner's copy of an announcement.
+    static ANNOUNCEMENT_NOTIFICATION_INDEX: RefCell<AnnouncementNotificationIndexStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(41)))
+        )
+    );
+}
+
+fn get_next_id() -> u64 {
+    ID_COUNTER.with(|counter| {
+        let current_id = counter.borrow().get(&0).unwrap_or(0);
+        let next_id = current_id + 1;
+        counter.borrow_mut().insert(0, next_id);
+        next_id
+    })
+}
+
+fn is_admin(principal: Principal) -> bool {
+    ic_cdk::api::is_controller(&principal)
+}
+
+fn ensure_course_owner(course_id: u64, caller: Principal) -> Result<Course, String> {
+    match COURSE_STORAGE.with(|storage| storage.borrow().get(&course_id)) {
+        Some(course) if course.educator == caller => Ok(course),
+        Some(_) => Err("Only the course owner can do this".to_string()),
+        None => Err("Course not found".to_string()),
+    }
+}
+
+fn has_permission(course: &Course, principal: Principal, permission: &StaffPermission) -> bool {
+    course.educator == principal
+        || staff_member(course.id, principal)
+            .is_some_and(|member| member.permissions.contains(permission))
+}
+
+fn ensure_permission(
+    course_id: u64,
+    caller: Principal,
+    permission: StaffPermission,
+) -> Result<Course, String> {
+    match COURSE_STORAGE.with(|storage| storage.borrow().get(&course_id)) {
+        Some(course) if has_permission(&course, caller, &permission) => Ok(course),
+        Some(_) => {
+            let action = match permission {
+                StaffPermission::EditContent => "edit this course",
+                StaffPermission::Grade => "grade this course",
+                StaffPermission::ManageEnrollments => "manage enrollments for this course",
+                StaffPermission::ViewAnalytics => "view analytics for this course",
+            };
+            Err(format!("You do not have permission to {}", action))
+        }
+        None => Err("Course not found".to_string()),
+    }
+}
+
+#[ic_cdk::update]
+fn create_course(payload: CreateCoursePayload) -> Result<Course, String> {
+    let caller = ic_cdk::caller();
+    
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot create courses".to_string());
+    }
+
+    let default_locale = match &payload.default_locale {
+        Some(locale) => normalize_locale(locale)?,
+        None => DEFAULT_LOCALE.to_string(),
+    };
+
+    let course_id = get_next_id();
+    validate_prerequisites(course_id, &payload.prerequisites)?;
+
+    let lessons: Vec<Lesson> = payload
+        .lessons
+        .into_iter()
+        .enumerate()
+        .map(|(index, lesson_payload)| {
+            build_lesson(course_id, (index + 1) as u64, lesson_payload)
+        })
+        .collect::<Result<_, _>>()?;
+    let duration_secs = lessons.iter().map(|lesson| lesson.duration_secs).sum();
+
+    let course = Course {
+        id: course_id,
+        title: payload.title,
+        description: payload.description,
+        educator: caller,
+        lessons,
+        level: payload.level,
+        image: payload.image,
+        tags: normalize_tags(&payload.tags),
+        duration_secs,
+        prerequisites: payload.prerequisites,
+        published: payload.published,
+        enrollment_policy: payload.enrollment_policy,
+        price: payload.price,
+        reviews_require_completion: false,
+        default_locale,
+        rating_total: 0,
+        rating_count: 0,
+        average_rating: 0.0,
+        enrolled_students: 0,
+        created_at: time(),
+        updated_at: time(),
+    };
+
+    COURSE_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(course_id, course.clone());
+    });
+    EDUCATOR_COURSE_INDEX.with(|index| {
+        index.borrow_mut().insert((caller, course_id), ());
+    });
+
+    add_to_catalog(&course);
+
+    Ok(course)
+}
+
+fn get_course(course_id: u64) -> Option<Course> {
+    COURSE_STORAGE.with(|storage| storage.borrow().get(&course_id))
+}
+
+// Course listings leave lesson content out for anyone but course staff, so
+// locked lessons can only be read through get_lesson.
+fn public_course_view(mut course: Course, caller: Principal) -> Course {
+    if course.educator != caller && staff_member(course.id, caller).is_none() {
+        for lesson in course.lessons.iter_mut() {
+            lesson.content.clear();
+        }
+    }
+    course
+}
+
+#[ic_cdk::query(name = "get_course")]
+fn get_course_view(course_id: u64) -> Option<Course> {
+    get_course(course_id).map(|course| public_course_view(course, ic_cdk::caller()))
+}
+
+#[ic_cdk::query]
+fn get_all_courses() -> Vec<Course> {
+    let caller = ic_cdk::caller();
+
+    COURSE_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .map(|(_, course)| public_course_view(course, caller))
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_courses_by_educator(educator: Principal) -> Vec<Course> {
+    let caller = ic_cdk::caller();
+
+    educator_course_ids(educator)
+        .into_iter()
+        .filter_map(get_course)
+        .map(|course| public_course_view(course, caller))
+        .collect()
+}
+
+#[ic_cdk::update]
+fn update_course(course_id: u64, payload: UpdateCoursePayload) -> Result<Course, String> {
+    let caller = ic_cdk::caller();
+
+    ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+    if payload.enrollment_policy.is_some() {
+        ensure_permission(course_id, caller, StaffPermission::ManageEnrollments)?;
+    }
+    if payload.price.is_some() {
+        ensure_course_owner(course_id, caller)?;
+    }
+
+    if let Some(prerequisites) = &payload.prerequisites {
+        validate_prerequisites(course_id, prerequisites)?;
+    }
+    let default_locale = match &payload.default_locale {
+        Some(locale) => {
+            let locale = normalize_locale(locale)?;
+            if get_course_translation(course_id, locale.clone()).is_some() {
+                return Err(format!("Remove the {} translation before making it the default locale", locale));
+            }
+            Some(locale)
+        }
+        None => None,
+    };
+    
+    COURSE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        match storage.get(&course_id) {
+            Some(mut course) => {
+                let previous = course.clone();
+
+                if let Some(title) = payload.title {
+                    course.title = title;
+                }
+                if let Some(description) = payload.description {
+                    course.description = description;
+                }
+                if let Some(level) = payload.level {
+                    course.level = level;
+                }
+                if let Some(image) = payload.image {
+                    course.image = image;
+                }
+                if let Some(tags) = payload.tags {
+                    course.tags = normalize_tags(&tags);
+                }
+                if let Some(prerequisites) = payload.prerequisites {
+                    course.prerequisites = prerequisites;
+                }
+                if let Some(published) = payload.published {
+                    course.published = published;
+                }
+                if let Some(enrollment_policy) = payload.enrollment_policy {
+                    course.enrollment_policy = enrollment_policy;
+                }
+                if let Some(price) = payload.price {
+                    course.price = price;
+                }
+                if let Some(reviews_require_completion) = payload.reviews_require_completion {
+                    course.reviews_require_completion = reviews_require_completion;
+                }
+                if let Some(default_locale) = default_locale {
+                    course.default_locale = default_locale;
+                }
+                course.updated_at = time();
+                
+                storage.insert(course_id, course.clone());
+                remove_from_catalog(&previous);
+                add_to_catalog(&course);
+                Ok(course)
+            }
+            None => Err("Course not found".to_string()),
+        }
+    })
+}
+
+#[ic_cdk::update]
+async fn enroll_in_course(course_id: u64) -> Result<Enrollment, String> {
+    let caller = ic_cdk::caller();
+    
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot enroll in courses".to_string());
+    }
+
+    // Check if course exists
+    let course = match COURSE_STORAGE.with(|storage| storage.borrow().get(&course_id)) {
+        Some(course) => course,
+        None => return Err("Course not found".to_string()),
+    };
+
+    ensure_open_enrollment(&course)?;
+
+    if course_has_cohorts(course_id) {
+        return Err("This course runs in cohorts; enroll in a cohort instead".to_string());
+    }
+
+    if course.price == 0 {
+        return admit_student(caller, &course, None);
+    }
+
+    check_admission(caller, &course, None)?;
+    let purchase = charge_for_course(caller, &course).await?;
+    admit_paid_student(caller, &course, None, purchase).await
+}
+
+// Stores a new enrollment and bumps the course's student count; callers are
+// responsible for eligibility checks.
+fn record_enrollment(student: Principal, course_id: u64, cohort_id: Option<u64>) -> Enrollment {
+    let enrollment = Enrollment {
+        student,
+        course_id,
+        enrolled_at: time(),
+        cohort_id,
+        purchase_id: None,
+        completed: false,
+        completion_date: None,
+        final_grade: None,
+        certificate_id: None,
+        reward_transaction_id: None,
+    };
+
+    ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((student, course_id), enrollment.clone());
+    });
+    COURSE_STUDENT_INDEX.with(|index| {
+        index.borrow_mut().insert((course_id, student), ());
+    });
+
+    // Update enrolled students count
+    COURSE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(mut course) = storage.get(&course_id) {
+            course.enrolled_students += 1;
+            storage.insert(course_id, course);
+        }
+    });
+
+    enrollment
+}
+
+// Reverses `record_enrollment`, releasing the cohort seat if there was one.
+fn remove_enrollment(student: Principal, course_id: u64) -> Option<Enrollment> {
+    let enrollment = ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow_mut().remove(&(student, course_id))
+    })?;
+    COURSE_STUDENT_INDEX.with(|index| {
+        index.borrow_mut().remove(&(course_id, student));
+    });
+
+    COURSE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(mut course) = storage.get(&course_id) {
+            course.enrolled_students = course.enrolled_students.saturating_sub(1);
+            storage.insert(course_id, course);
+        }
+    });
+
+    if let Some(cohort_id) = enrollment.cohort_id {
+        adjust_cohort_seats(cohort_id, -1);
+        promote_from_waitlist(cohort_id);
+    }
+
+    Some(enrollment)
+}
+
+#[ic_cdk::update]
+fn complete_lesson(course_id: u64, lesson_id: u64) -> Result<LessonProgress, String> {
+    let caller = ic_cdk::caller();
+    
+    // Check if enrolled in course
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(caller, course_id)))
+        .ok_or_else(|| "Not enrolled in this course".to_string())?;
+    let lesson = get_course(course_id)
+        .and_then(|course| course.lessons.into_iter().find(|lesson| lesson.id == lesson_id))
+        .ok_or_else(|| "Lesson not found".to_string())?;
+    ensure_lesson_unlocked(&lesson, &enrollment)?;
+
+    let now = time();
+    let progress_key = (caller, course_id, lesson_id);
+    let progress = LessonProgress {
+        student: caller,
+        course_id,
+        lesson_id,
+        completed: true,
+        completed_at: Some(now),
+        late: lesson_due_at(&lesson, &enrollment).is_some_and(|due_at| now > due_at),
+    };
+
+    PROGRESS_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(progress_key, progress.clone());
+    });
+
+    Ok(progress)
+}
+
+// Marks the enrollment complete, then requests the certificate and reward.
+// Failed calls are queued for retry and never undo the completion.
+#[ic_cdk::update]
+async fn complete_course(course_id: u64) -> Result<Enrollment, String> {
+    let caller = ic_cdk::caller();
+    
+    let enrollment_key = (caller, course_id);
+    let existing = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&enrollment_key))
+        .ok_or_else(|| "Not enrolled in this course".to_string())?;
+
+    if existing.completed {
+        return Err("Course already completed".to_string());
+    }
+
+    // Branching courses only require the lessons on the learner's own route,
+    // which is finished once there is no next lesson to take.
+    let course = get_course(course_id).ok_or_else(|| "Course not found".to_string())?;
+    if adaptive_next_lesson(&existing, &course).is_some() {
+        return Err("All lessons must be completed before completing the course".to_string());
+    }
+
+    check_required_assignments(caller, course_id)?;
+
+    let final_grade = compute_final_grade(caller, course_id).ok();
+    if let Some(grade) = &final_grade {
+        if !grade.passed && gradebook_config(course_id).require_passing_grade {
+            return Err("A passing final grade is required to complete this course".to_string());
+        }
+    }
+
+    let enrollment = ENROLLMENT_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        match storage.get(&enrollment_key) {
+            Some(mut enrollment) => {
+                enrollment.completed = true;
+                enrollment.completion_date = Some(time());
+                enrollment.final_grade = final_grade;
+                storage.insert(enrollment_key, enrollment.clone());
+                Ok(enrollment)
+            }
+            None => Err("Not enrolled in this course".to_string()),
+        }
+    })?;
+
+    record_path_completions(caller, course_id);
+
+    if enrollment.certificate_id.is_some() && enrollment.reward_transaction_id.is_some() {
+        return Ok(enrollment);
+    }
+
+    queue_completion_dispatch(caller, CompletionKind::Course, course_id);
+    dispatch_completion(caller, course_id).await;
+
+    Ok(ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&enrollment_key))
+        .unwrap_or(enrollment))
+}
+
+#[ic_cdk::query]
+fn get_student_enrollments(student: Principal) -> Vec<Enrollment> {
+    ENROLLMENT_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((student, 0)..=(student, u64::MAX))
+            .map(|(_, enrollment)| enrollment)
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_course_enrollments(course_id: u64) -> Vec<Enrollment> {
+    course_student_ids(course_id)
+        .into_iter()
+        .filter_map(|student| ENROLLMENT_STORAGE.with(|storage| storage.borrow().get(&(student, course_id))))
+        .collect()
+}
+
+#[ic_cdk::query]
+fn get_lesson_progress(student: Principal, course_id: u64) -> Vec<LessonProgress> {
+    student_course_progress(student, course_id)
+}
+
+// Enrollment Indexes
+// Both indexes are written alongside the records they point at:
+// `record_enrollment`/`remove_enrollment` for course -> students and course
+// creation for educator -> courses. Progress rows are already keyed by
+// (student, course, lesson) and are range-scanned directly.
+fn course_student_ids(course_id: u64) -> Vec<Principal> {
+    COURSE_STUDENT_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((course_id, Principal::management_canister())..)
+            .take_while(|((c_id, _), _)| *c_id == course_id)
+            .map(|((_, student), _)| student)
+            .collect()
+    })
+}
+
+fn educator_course_ids(educator: Principal) -> Vec<u64> {
+    EDUCATOR_COURSE_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((educator, 0)..=(educator, u64::MAX))
+            .map(|((_, course_id), _)| course_id)
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_course_enrollments_page(course_id: u64, offset: u32, limit: u32) -> EnrollmentPage {
+    COURSE_STUDENT_INDEX.with(|index| {
+        let index = index.borrow();
+        let students = || {
+            index
+                .range((course_id, Principal::management_canister())..)
+                .take_while(|((c_id, _), _)| *c_id == course_id)
+                .map(|((_, student), _)| student)
+        };
+
+        EnrollmentPage {
+            total: students().count() as u64,
+            enrollments: students()
+                .skip(offset as usize)
+                .take(page_limit(limit))
+                .filter_map(|student| ENROLLMENT_STORAGE.with(|storage| storage.borrow().get(&(student, course_id))))
+                .collect(),
+        }
+    })
+}
+
+#[ic_cdk::query]
+fn get_courses_by_educator_page(educator: Principal, offset: u32, limit: u32) -> CoursePage {
+    let caller = ic_cdk::caller();
+    let course_ids = educator_course_ids(educator);
+
+    CoursePage {
+        total: course_ids.len() as u64,
+        courses: course_ids
+            .into_iter()
+            .skip(offset as usize)
+            .take(page_limit(limit))
+            .filter_map(get_course)
+            .map(|course| public_course_view(course, caller))
+            .collect(),
+    }
+}
+
+#[ic_cdk::query]
+fn get_lesson_progress_page(student: Principal, course_id: u64, offset: u32, limit: u32) -> LessonProgressPage {
+    PROGRESS_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        let rows = || storage.range((student, course_id, 0)..=(student, course_id, u64::MAX));
+
+        LessonProgressPage {
+            total: rows().count() as u64,
+            progress: rows()
+                .skip(offset as usize)
+                .take(page_limit(limit))
+                .map(|(_, progress)| progress)
+                .collect(),
+        }
+    })
+}
+
+// Refills both indexes from the primary records. Needed once when upgrading
+// from a version without them, which `post_upgrade` does automatically.
+fn rebuild_enrollment_index_entries() -> u64 {
+    COURSE_STUDENT_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let keys: Vec<(u64, Principal)> = index.iter().map(|(key, _)| key).collect();
+        for key in keys {
+            index.remove(&key);
+        }
+    });
+    EDUCATOR_COURSE_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let keys: Vec<(Principal, u64)> = index.iter().map(|(key, _)| key).collect();
+        for key in keys {
+            index.remove(&key);
+        }
+    });
+
+    let mut entries = 0;
+    ENROLLMENT_STORAGE.with(|storage| {
+        COURSE_STUDENT_INDEX.with(|index| {
+            let mut index = index.borrow_mut();
+            for ((student, course_id), _) in storage.borrow().iter() {
+                index.insert((course_id, student), ());
+                entries += 1;
+            }
+        })
+    });
+    COURSE_STORAGE.with(|storage| {
+        EDUCATOR_COURSE_INDEX.with(|index| {
+            let mut index = index.borrow_mut();
+            for (course_id, course) in storage.borrow().iter() {
+                index.insert((course.educator, course_id), ());
+                entries += 1;
+            }
+        })
+    });
+
+    entries
+}
+
+#[ic_cdk::update]
+fn rebuild_enrollment_indexes() -> Result<u64, String> {
+    let caller = ic_cdk::caller();
+
+    if !is_admin(caller) {
+        return Err("Only admins can rebuild the enrollment indexes".to_string());
+    }
+
+    Ok(rebuild_enrollment_index_entries())
+}
+
+// Question Bank
+fn validate_bank_question(payload: &BankQuestionPayload) -> Result<(), String> {
+    if payload.prompt.trim().is_empty() {
+        return Err("Question prompt cannot be empty".to_string());
+    }
+    if payload.options.len() < 2 {
+        return Err("A question needs at least two options".to_string());
+    }
+    if payload.correct_option as usize >= payload.options.len() {
+        return Err("Correct option is out of range".to_string());
+    }
+    if payload.points == 0 || payload.points > MAX_QUESTION_POINTS {
+        return Err(format!("Question points must be between 1 and {}", MAX_QUESTION_POINTS));
+    }
+    Ok(())
+}
+
+#[ic_cdk::update]
+fn add_bank_question(course_id: u64, payload: BankQuestionPayload) -> Result<BankQuestion, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+    validate_bank_question(&payload)?;
+
+    let question_id = get_next_id();
+    let question = BankQuestion {
+        id: question_id,
+        course_id,
+        topic: payload.topic.trim().to_string(),
+        difficulty: payload.difficulty,
+        prompt: payload.prompt,
+        options: payload.options,
+        correct_option: payload.correct_option,
+        points: payload.points,
+        created_at: time(),
+        updated_at: time(),
+    };
+
+    QUESTION_BANK_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((course_id, question_id), question.clone());
+    });
+
+    Ok(question)
+}
+
+#[ic_cdk::update]
+fn update_bank_question(
+    course_id: u64,
+    question_id: u64,
+    payload: BankQuestionPayload,
+) -> Result<BankQuestion, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+    validate_bank_question(&payload)?;
+
+    QUESTION_BANK_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        match storage.get(&(course_id, question_id)) {
+            Some(mut question) => {
+                question.topic = payload.topic.trim().to_string();
+                question.difficulty = payload.difficulty;
+                question.prompt = payload.prompt;
+                question.options = payload.options;
+                question.correct_option = payload.correct_option;
+                question.points = payload.points;
+                question.updated_at = time();
+
+                storage.insert((course_id, question_id), question.clone());
+                Ok(question)
+            }
+            None => Err("Question not found".to_string()),
+        }
+    })
+}
+
+#[ic_cdk::update]
+fn remove_bank_question(course_id: u64, question_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    QUESTION_BANK_STORAGE.with(|storage| {
+        match storage.borrow_mut().remove(&(course_id, question_id)) {
+            Some(_) => Ok("Question removed successfully".to_string()),
+            None => Err("Question not found".to_string()),
+        }
+    })
+}
+
+#[ic_cdk::query]
+fn get_question_bank(course_id: u64) -> Result<Vec<BankQuestion>, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    Ok(course_bank_questions(course_id))
+}
+
+fn course_bank_questions(course_id: u64) -> Vec<BankQuestion> {
+    QUESTION_BANK_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((course_id, 0)..=(course_id, u64::MAX))
+            .map(|(_, question)| question)
+            .collect()
+    })
+}
+
+fn section_matches(section: &ExamSection, question: &BankQuestion) -> bool {
+    let topic_matches = match &section.topic {
+        Some(topic) => topic.trim().eq_ignore_ascii_case(&question.topic),
+        None => true,
+    };
+    let difficulty_matches = match &section.difficulty {
+        Some(difficulty) => *difficulty == question.difficulty,
+        None => true,
+    };
+    topic_matches && difficulty_matches
+}
+
+// Small deterministic generator seeded from `raw_rand`, so a paper can be
+// reproduced from the seed stored on the attempt.
+struct ExamRng {
+    state: u64,
+}
+
+impl ExamRng {
+    fn from_seed(seed: &[u8]) -> Self {
+        let state = seed
+            .iter()
+            .fold(0x9e37_79b9_7f4a_7c15u64, |acc, &b| acc.rotate_left(8) ^ b as u64);
+        ExamRng { state }
+    }
+
+    // splitmix64
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+// Draws each section independently without reusing a question across
+// sections, and shuffles the answer options of every drawn question.
+fn draw_exam_questions(
+    exam: &Exam,
+    bank: &[BankQuestion],
+    rng: &mut ExamRng,
+) -> Result<Vec<ExamAttemptQuestion>, String> {
+    let mut drawn: Vec<ExamAttemptQuestion> = Vec::new();
+
+    for (index, section) in exam.sections.iter().enumerate() {
+        let mut candidates: Vec<&BankQuestion> = bank
+            .iter()
+            .filter(|q| section_matches(section, q))
+            .filter(|q| !drawn.iter().any(|d| d.question_id == q.id))
+            .collect();
+
+        if candidates.len() < section.question_count as usize {
+            return Err(format!(
+                "Question bank has only {} questions for section {}, {} required",
+                candidates.len(),
+                index + 1,
+                section.question_count
+            ));
+        }
+
+        rng.shuffle(&mut candidates);
+        for question in candidates.into_iter().take(section.question_count as usize) {
+            let mut order: Vec<usize> = (0..question.options.len()).collect();
+            rng.shuffle(&mut order);
+            let correct_option = order
+                .iter()
+                .position(|&original| original == question.correct_option as usize)
+                .unwrap_or(0) as u32;
+
+            drawn.push(ExamAttemptQuestion {
+                question_id: question.id,
+                topic: question.topic.clone(),
+                difficulty: question.difficulty.clone(),
+                prompt: question.prompt.clone(),
+                options: order.iter().map(|&i| question.options[i].clone()).collect(),
+                correct_option,
+                points: question.points,
+                selected_option: None,
+            });
+        }
+    }
+
+    Ok(drawn)
+}
+
+// Exams
+fn validate_exam_payload(lessons: &[Lesson], payload: &CreateExamPayload) -> Result<(), String> {
+    if payload.title.trim().is_empty() {
+        return Err("Exam title cannot be empty".to_string());
+    }
+    if payload.sections.is_empty() || payload.sections.iter().any(|s| s.question_count == 0) {
+        return Err("An exam needs at least one section with questions".to_string());
+    }
+    if payload.time_limit_secs == 0 || payload.time_limit_secs > MAX_EXAM_TIME_LIMIT_SECS {
+        return Err(format!("Time limit must be between 1 and {} seconds", MAX_EXAM_TIME_LIMIT_SECS));
+    }
+    if payload.max_attempts == 0 {
+        return Err("Max attempts must be greater than zero".to_string());
+    }
+    if payload.passing_score > 100 {
+        return Err("Passing score must be a percentage between 0 and 100".to_string());
+    }
+    if let ExamKind::LessonQuiz { lesson_id } = payload.kind {
+        if !lessons.iter().any(|lesson| lesson.id == lesson_id) {
+            return Err("Lesson not found".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[ic_cdk::update]
+fn create_exam(payload: CreateExamPayload) -> Result<Exam, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_permission(payload.course_id, caller, StaffPermission::EditContent)?;
+    validate_exam_payload(&course.lessons, &payload)?;
+
+    let exam_id = get_next_id();
+    let exam = Exam {
+        id: exam_id,
+        course_id: payload.course_id,
+        title: payload.title,
+        kind: payload.kind,
+        sections: payload.sections,
+        time_limit_secs: payload.time_limit_secs,
+        max_attempts: payload.max_attempts,
+        passing_score: payload.passing_score,
+        created_at: time(),
+        updated_at: time(),
+    };
+
+    // Fail early if the bank cannot currently fill every section.
+    let bank = course_bank_questions(exam.course_id);
+    draw_exam_questions(&exam, &bank, &mut ExamRng::from_seed(&[]))?;
+
+    EXAM_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(exam_id, exam.clone());
+    });
+
+    Ok(exam)
+}
+
+#[ic_cdk::query]
+fn get_exam(exam_id: u64) -> Option<Exam> {
+    EXAM_STORAGE.with(|storage| storage.borrow().get(&exam_id))
+}
+
+#[ic_cdk::query]
+fn get_course_exams(course_id: u64) -> Vec<Exam> {
+    EXAM_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, exam)| {
+                if exam.course_id == course_id {
+                    Some(exam)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+fn student_exam_attempts(student: Principal, exam_id: u64) -> Vec<ExamAttempt> {
+    EXAM_ATTEMPT_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, attempt)| {
+                if attempt.student == student && attempt.exam_id == exam_id {
+                    Some(attempt)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+fn to_exam_paper(exam: &Exam, attempt: &ExamAttempt) -> ExamPaper {
+    ExamPaper {
+        attempt_id: attempt.id,
+        exam_id: exam.id,
+        title: exam.title.clone(),
+        questions: attempt
+            .questions
+            .iter()
+            .map(|q| ExamPaperQuestion {
+                question_id: q.question_id,
+                prompt: q.prompt.clone(),
+                options: q.options.clone(),
+                points: q.points,
+            })
+            .collect(),
+        started_at: attempt.started_at,
+        deadline: attempt.deadline,
+    }
+}
+
+fn to_exam_result(attempt: &ExamAttempt) -> ExamResult {
+    ExamResult {
+        attempt_id: attempt.id,
+        exam_id: attempt.exam_id,
+        attempt_number: attempt.attempt_number,
+        status: attempt.status.clone(),
+        score: attempt.score,
+        max_score: attempt.max_score,
+        passed: attempt.passed,
+        started_at: attempt.started_at,
+        submitted_at: attempt.submitted_at,
+    }
+}
+
+// Marks an in-progress attempt whose deadline has passed as expired.
+fn expire_if_overdue(attempt: &mut ExamAttempt, now: u64) -> bool {
+    if attempt.status == ExamAttemptStatus::InProgress && now > attempt.deadline {
+        attempt.status = ExamAttemptStatus::Expired;
+        attempt.score = 0;
+        attempt.passed = false;
+        EXAM_ATTEMPT_STORAGE.with(|storage| {
+            storage.borrow_mut().insert(attempt.id, attempt.clone());
+        });
+        return true;
+    }
+    false
+}
+
+// Returns an in-progress paper if one exists, otherwise records whether a new
+// attempt may be started.
+fn prepare_exam_attempt(student: Principal, exam: &Exam) -> Result<Option<ExamPaper>, String> {
+    let now = time();
+    let mut attempts = student_exam_attempts(student, exam.id);
+    for attempt in attempts.iter_mut() {
+        expire_if_overdue(attempt, now);
+        if attempt.status == ExamAttemptStatus::InProgress {
+            return Ok(Some(to_exam_paper(exam, attempt)));
+        }
+    }
+
+    if attempts.len() as u32 >= exam.max_attempts {
+        return Err("No attempts remaining for this exam".to_string());
+    }
+    Ok(None)
+}
+
+#[ic_cdk::update]
+async fn start_exam(exam_id: u64) -> Result<ExamPaper, String> {
+    let caller = ic_cdk::caller();
+
+    let exam = EXAM_STORAGE
+        .with(|storage| storage.borrow().get(&exam_id))
+        .ok_or_else(|| "Exam not found".to_string())?;
+
+    let is_enrolled = ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&(caller, exam.course_id))
+    });
+    if !is_enrolled {
+        return Err("Not enrolled in this course".to_string());
+    }
+    if let ExamKind::LessonQuiz { lesson_id } = exam.kind {
+        check_lesson_unlocked(caller, exam.course_id, lesson_id)?;
+    }
+
+    if let Some(paper) = prepare_exam_attempt(caller, &exam)? {
+        return Ok(paper);
+    }
+
+    let (seed,) = raw_rand()
+        .await
+        .map_err(|(code, msg)| format!("Failed to obtain randomness: {:?} {}", code, msg))?;
+
+    // State may have changed while awaiting randomness; re-check.
+    if let Some(paper) = prepare_exam_attempt(caller, &exam)? {
+        return Ok(paper);
+    }
+
+    let bank = course_bank_questions(exam.course_id);
+    let questions = draw_exam_questions(&exam, &bank, &mut ExamRng::from_seed(&seed))?;
+    let max_score = questions.iter().map(|q| q.points).sum();
+    let attempt_number = student_exam_attempts(caller, exam_id).len() as u32 + 1;
+
+    let started_at = time();
+    let attempt = ExamAttempt {
+        id: get_next_id(),
+        exam_id,
+        course_id: exam.course_id,
+        student: caller,
+        attempt_number,
+        seed,
+        questions,
+        started_at,
+        deadline: started_at.saturating_add(exam.time_limit_secs.saturating_mul(NANOS_PER_SECOND)),
+        submitted_at: None,
+        status: ExamAttemptStatus::InProgress,
+        score: 0,
+        max_score,
+        passed: false,
+    };
+
+    EXAM_ATTEMPT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(attempt.id, attempt.clone());
+    });
+
+    Ok(to_exam_paper(&exam, &attempt))
+}
+
+#[ic_cdk::update]
+fn submit_exam(attempt_id: u64, answers: Vec<ExamAnswer>) -> Result<ExamResult, String> {
+    let caller = ic_cdk::caller();
+
+    let mut attempt = EXAM_ATTEMPT_STORAGE
+        .with(|storage| storage.borrow().get(&attempt_id))
+        .ok_or_else(|| "Exam attempt not found".to_string())?;
+
+    if attempt.student != caller {
+        return Err("Only the student who started this attempt can submit it".to_string());
+    }
+    if attempt.status != ExamAttemptStatus::InProgress {
+        return Err("Exam attempt has already been closed".to_string());
+    }
+    if expire_if_overdue(&mut attempt, time()) {
+        return Err("Time limit exceeded; the attempt has expired".to_string());
+    }
+
+    let passing_score = EXAM_STORAGE
+        .with(|storage| storage.borrow().get(&attempt.exam_id))
+        .map(|exam| exam.passing_score)
+        .unwrap_or(100);
+
+    let mut score = 0;
+    for question in attempt.questions.iter_mut() {
+        question.selected_option = answers
+            .iter()
+            .find(|answer| answer.question_id == question.question_id)
+            .map(|answer| answer.selected_option);
+        if question.selected_option == Some(question.correct_option) {
+            score += question.points;
+        }
+    }
+
+    attempt.score = score;
+    attempt.passed = attempt.max_score > 0
+        && score as u64 * 100 >= passing_score as u64 * attempt.max_score as u64;
+    attempt.status = ExamAttemptStatus::Submitted;
+    attempt.submitted_at = Some(time());
+
+    EXAM_ATTEMPT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(attempt_id, attempt.clone());
+    });
+
+    Ok(to_exam_result(&attempt))
+}
+
+#[ic_cdk::query]
+fn get_my_exam_attempts(exam_id: u64) -> Vec<ExamResult> {
+    let caller = ic_cdk::caller();
+    student_exam_attempts(caller, exam_id)
+        .iter()
+        .map(to_exam_result)
+        .collect()
+}
+
+#[ic_cdk::query]
+fn get_exam_attempts(exam_id: u64) -> Result<Vec<ExamAttempt>, String> {
+    let caller = ic_cdk::caller();
+
+    let exam = EXAM_STORAGE
+        .with(|storage| storage.borrow().get(&exam_id))
+        .ok_or_else(|| "Exam not found".to_string())?;
+    ensure_permission(exam.course_id, caller, StaffPermission::Grade)?;
+
+    Ok(EXAM_ATTEMPT_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, attempt)| {
+                if attempt.exam_id == exam_id {
+                    Some(attempt)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }))
+}
+
+// Assignments
+fn validate_rubric(rubric: &[RubricCriterion], passing_points: u32) -> Result<(), String> {
+    if rubric.is_empty() {
+        return Err("An assignment needs at least one rubric criterion".to_string());
+    }
+    if rubric.iter().any(|c| c.name.trim().is_empty() || c.max_points == 0) {
+        return Err("Rubric criteria need a name and positive max points".to_string());
+    }
+    let max_points: u32 = rubric.iter().map(|c| c.max_points).sum();
+    if passing_points > max_points {
+        return Err("Passing points cannot exceed the rubric maximum".to_string());
+    }
+    Ok(())
+}
+
+#[ic_cdk::update]
+fn create_assignment(payload: CreateAssignmentPayload) -> Result<Assignment, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_permission(payload.course_id, caller, StaffPermission::EditContent)?;
+
+    if !course.lessons.iter().any(|lesson| lesson.id == payload.lesson_id) {
+        return Err("Lesson not found".to_string());
+    }
+    if payload.title.trim().is_empty() {
+        return Err("Assignment title cannot be empty".to_string());
+    }
+    if payload.max_submissions == 0 {
+        return Err("Max submissions must be greater than zero".to_string());
+    }
+    validate_rubric(&payload.rubric, payload.passing_points)?;
+
+    let assignment_id = get_next_id();
+    let assignment = Assignment {
+        id: assignment_id,
+        course_id: payload.course_id,
+        lesson_id: payload.lesson_id,
+        title: payload.title,
+        instructions: payload.instructions,
+        rubric: payload.rubric,
+        passing_points: payload.passing_points,
+        allow_resubmission: payload.allow_resubmission,
+        max_submissions: payload.max_submissions,
+        required_for_completion: payload.required_for_completion,
+        created_at: time(),
+        updated_at: time(),
+    };
+
+    ASSIGNMENT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(assignment_id, assignment.clone());
+    });
+
+    Ok(assignment)
+}
+
+#[ic_cdk::update]
+fn update_assignment(assignment_id: u64, payload: UpdateAssignmentPayload) -> Result<Assignment, String> {
+    let caller = ic_cdk::caller();
+
+    let mut assignment = ASSIGNMENT_STORAGE
+        .with(|storage| storage.borrow().get(&assignment_id))
+        .ok_or_else(|| "Assignment not found".to_string())?;
+    ensure_permission(assignment.course_id, caller, StaffPermission::EditContent)?;
+
+    if let Some(title) = payload.title {
+        assignment.title = title;
+    }
+    if let Some(instructions) = payload.instructions {
+        assignment.instructions = instructions;
+    }
+    if let Some(rubric) = payload.rubric {
+        assignment.rubric = rubric;
+    }
+    if let Some(passing_points) = payload.passing_points {
+        assignment.passing_points = passing_points;
+    }
+    if let Some(allow_resubmission) = payload.allow_resubmission {
+        assignment.allow_resubmission = allow_resubmission;
+    }
+    if let Some(max_submissions) = payload.max_submissions {
+        if max_submissions == 0 {
+            return Err("Max submissions must be greater than zero".to_string());
+        }
+        assignment.max_submissions = max_submissions;
+    }
+    if let Some(required_for_completion) = payload.required_for_completion {
+        assignment.required_for_completion = required_for_completion;
+    }
+    validate_rubric(&assignment.rubric, assignment.passing_points)?;
+    assignment.updated_at = time();
+
+    ASSIGNMENT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(assignment_id, assignment.clone());
+    });
+
+    Ok(assignment)
+}
+
+#[ic_cdk::query]
+fn get_course_assignments(course_id: u64) -> Vec<Assignment> {
+    ASSIGNMENT_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, assignment)| {
+                if assignment.course_id == course_id {
+                    Some(assignment)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+fn student_submissions(student: Principal, assignment_id: u64) -> Vec<AssignmentSubmission> {
+    let mut submissions: Vec<AssignmentSubmission> = SUBMISSION_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, submission)| {
+                if submission.student == student && submission.assignment_id == assignment_id {
+                    Some(submission)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    });
+    submissions.sort_by_key(|submission| submission.submission_number);
+    submissions
+}
+
+#[ic_cdk::update]
+fn submit_assignment(
+    assignment_id: u64,
+    payload: SubmitAssignmentPayload,
+) -> Result<AssignmentSubmission, String> {
+    let caller = ic_cdk::caller();
+
+    let assignment = ASSIGNMENT_STORAGE
+        .with(|storage| storage.borrow().get(&assignment_id))
+        .ok_or_else(|| "Assignment not found".to_string())?;
+
+    let is_enrolled = ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&(caller, assignment.course_id))
+    });
+    if !is_enrolled {
+        return Err("Not enrolled in this course".to_string());
+    }
+    check_lesson_unlocked(caller, assignment.course_id, assignment.lesson_id)?;
+
+    if payload.text.trim().is_empty() && payload.file_references.is_empty() {
+        return Err("Submission must include text or file references".to_string());
+    }
+    if payload.file_references.iter().any(|file| file.trim().is_empty()) {
+        return Err("File references cannot be empty".to_string());
+    }
+
+    let previous = student_submissions(caller, assignment_id);
+    if let Some(latest) = previous.last() {
+        if !assignment.allow_resubmission {
+            return Err("Resubmission is not allowed for this assignment".to_string());
+        }
+        if latest.grade.is_none() {
+            return Err("Previous submission is still awaiting grading".to_string());
+        }
+        if previous.len() as u32 >= assignment.max_submissions {
+            return Err("No submissions remaining for this assignment".to_string());
+        }
+    }
+
+    let submission_id = get_next_id();
+    let submission = AssignmentSubmission {
+        id: submission_id,
+        assignment_id,
+        course_id: assignment.course_id,
+        student: caller,
+        submission_number: previous.len() as u32 + 1,
+        text: payload.text,
+        file_references: payload.file_references,
+        submitted_at: time(),
+        grade: None,
+    };
+
+    SUBMISSION_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(submission_id, submission.clone());
+    });
+
+    Ok(submission)
+}
+
+#[ic_cdk::update]
+fn grade_submission(
+    submission_id: u64,
+    payload: GradeSubmissionPayload,
+) -> Result<AssignmentSubmission, String> {
+    let caller = ic_cdk::caller();
+
+    let mut submission = SUBMISSION_STORAGE
+        .with(|storage| storage.borrow().get(&submission_id))
+        .ok_or_else(|| "Submission not found".to_string())?;
+    ensure_permission(submission.course_id, caller, StaffPermission::Grade)?;
+
+    let assignment = ASSIGNMENT_STORAGE
+        .with(|storage| storage.borrow().get(&submission.assignment_id))
+        .ok_or_else(|| "Assignment not found".to_string())?;
+
+    if payload.scores.iter().any(|s| s.criterion as usize >= assignment.rubric.len()) {
+        return Err("Score refers to an unknown rubric criterion".to_string());
+    }
+
+    // Every rubric criterion must be scored exactly once.
+    for (index, criterion) in assignment.rubric.iter().enumerate() {
+        let mut scores = payload.scores.iter().filter(|s| s.criterion as usize == index);
+        match (scores.next(), scores.next()) {
+            (Some(score), None) if score.points <= criterion.max_points => {}
+            (Some(_), None) => {
+                return Err(format!("Score for '{}' exceeds its maximum", criterion.name));
+            }
+            (None, _) => return Err(format!("Missing score for '{}'", criterion.name)),
+            (Some(_), Some(_)) => {
+                return Err(format!("Duplicate score for '{}'", criterion.name));
+            }
+        }
+    }
+
+    let total_points: u32 = payload.scores.iter().map(|s| s.points).sum();
+    submission.grade = Some(AssignmentGrade {
+        grader: caller,
+        scores: payload.scores,
+        total_points,
+        max_points: assignment.rubric.iter().map(|c| c.max_points).sum(),
+        passed: total_points >= assignment.passing_points,
+        feedback: payload.feedback,
+        graded_at: time(),
+    });
+
+    SUBMISSION_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(submission_id, submission.clone());
+    });
+
+    Ok(submission)
+}
+
+#[ic_cdk::query]
+fn get_assignment_submissions(assignment_id: u64) -> Result<Vec<AssignmentSubmission>, String> {
+    let caller = ic_cdk::caller();
+
+    let assignment = ASSIGNMENT_STORAGE
+        .with(|storage| storage.borrow().get(&assignment_id))
+        .ok_or_else(|| "Assignment not found".to_string())?;
+    ensure_permission(assignment.course_id, caller, StaffPermission::Grade)?;
+
+    Ok(SUBMISSION_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, submission)| {
+                if submission.assignment_id == assignment_id {
+                    Some(submission)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }))
+}
+
+#[ic_cdk::query]
+fn get_student_assignment_grades(
+    course_id: u64,
+    student: Principal,
+) -> Result<Vec<AssignmentSubmission>, String> {
+    let caller = ic_cdk::caller();
+    if caller != student {
+        ensure_permission(course_id, caller, StaffPermission::Grade)?;
+    }
+
+    Ok(SUBMISSION_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, submission)| {
+                if submission.course_id == course_id && submission.student == student {
+                    Some(submission)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }))
+}
+
+#[ic_cdk::query]
+fn get_course_assignment_grades(course_id: u64) -> Result<Vec<AssignmentSubmission>, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::Grade)?;
+
+    Ok(SUBMISSION_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, submission)| {
+                if submission.course_id == course_id && submission.grade.is_some() {
+                    Some(submission)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }))
+}
+
+// A course can only be completed once the latest submission for every
+// required assignment has been graded as passing.
+fn check_required_assignments(student: Principal, course_id: u64) -> Result<(), String> {
+    for assignment in get_course_assignments(course_id) {
+        if !assignment.required_for_completion {
+            continue;
+        }
+        let passed = student_submissions(student, assignment.id)
+            .last()
+            .and_then(|submission| submission.grade.as_ref())
+            .map(|grade| grade.passed)
+            .unwrap_or(false);
+        if !passed {
+            return Err(format!("Required assignment '{}' has not been passed", assignment.title));
+        }
+    }
+    Ok(())
+}
+
+// Gradebook
+fn default_gradebook_config(course_id: u64) -> GradebookConfig {
+    let band = |letter: &str, min_percent: u32| GradeBand {
+        letter: letter.to_string(),
+        min_percent,
+    };
+    GradebookConfig {
+        course_id,
+        weights: GradeWeights {
+            quizzes: 30,
+            exams: 40,
+            assignments: 30,
+        },
+        bands: vec![band("A", 90), band("B", 80), band("C", 70), band("D", 60), band("F", 0)],
+        passing_percent: 60,
+        require_passing_grade: false,
+        updated_at: 0,
+    }
+}
+
+fn gradebook_config(course_id: u64) -> GradebookConfig {
+    GRADEBOOK_STORAGE
+        .with(|storage| storage.borrow().get(&course_id))
+        .unwrap_or_else(|| default_gradebook_config(course_id))
+}
+
+#[ic_cdk::update]
+fn set_gradebook_config(course_id: u64, payload: GradebookConfigPayload) -> Result<GradebookConfig, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    let weights = &payload.weights;
+    let total = weights
+        .quizzes
+        .checked_add(weights.exams)
+        .and_then(|total| total.checked_add(weights.assignments));
+    if total != Some(100) {
+        return Err("Grade weights must add up to 100".to_string());
+    }
+    if payload.passing_percent > 100 {
+        return Err("Passing percent must be between 0 and 100".to_string());
+    }
+    if payload.bands.iter().any(|b| b.letter.trim().is_empty() || b.min_percent > 100) {
+        return Err("Grade bands need a letter and a minimum between 0 and 100".to_string());
+    }
+    if !payload.bands.iter().any(|b| b.min_percent == 0) {
+        return Err("Grade bands must include a band starting at 0".to_string());
+    }
+
+    let mut bands = payload.bands;
+    bands.sort_by(|a, b| b.min_percent.cmp(&a.min_percent));
+
+    let config = GradebookConfig {
+        course_id,
+        weights: payload.weights,
+        bands,
+        passing_percent: payload.passing_percent,
+        require_passing_grade: payload.require_passing_grade,
+        updated_at: time(),
+    };
+
+    GRADEBOOK_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(course_id, config.clone());
+    });
+
+    Ok(config)
+}
+
+#[ic_cdk::query]
+fn get_gradebook_config(course_id: u64) -> Result<GradebookConfig, String> {
+    let course_exists = COURSE_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&course_id)
+    });
+
+    if !course_exists {
+        return Err("Course not found".to_string());
+    }
+
+    Ok(gradebook_config(course_id))
+}
+
+fn average(percents: &[f64]) -> Option<f64> {
+    if percents.is_empty() {
+        None
+    } else {
+        Some(percents.iter().sum::<f64>() / percents.len() as f64)
+    }
+}
+
+// Best submitted attempt per exam of the given kind; unattempted exams count as 0.
+fn exam_component(student: Principal, exams: &[Exam], quizzes: bool) -> Option<f64> {
+    let percents: Vec<f64> = exams
+        .iter()
+        .filter(|exam| matches!(exam.kind, ExamKind::LessonQuiz { .. }) == quizzes)
+        .map(|exam| {
+            student_exam_attempts(student, exam.id)
+                .iter()
+                .filter(|a| a.status == ExamAttemptStatus::Submitted && a.max_score > 0)
+                .map(|a| a.score as f64 * 100.0 / a.max_score as f64)
+                .fold(0.0, f64::max)
+        })
+        .collect();
+    average(&percents)
+}
+
+// Latest graded submission per assignment; missing or ungraded work counts as 0.
+fn assignment_component(student: Principal, course_id: u64) -> Option<f64> {
+    let percents: Vec<f64> = get_course_assignments(course_id)
+        .iter()
+        .map(|assignment| {
+            student_submissions(student, assignment.id)
+                .iter()
+                .rev()
+                .find_map(|submission| submission.grade.as_ref())
+                .filter(|grade| grade.max_points > 0)
+                .map(|grade| grade.total_points as f64 * 100.0 / grade.max_points as f64)
+                .unwrap_or(0.0)
+        })
+        .collect();
+    average(&percents)
+}
+
+fn compute_final_grade(student: Principal, course_id: u64) -> Result<FinalGrade, String> {
+    let config = gradebook_config(course_id);
+    let exams = get_course_exams(course_id);
+
+    let quiz_percent = exam_component(student, &exams, true);
+    let exam_percent = exam_component(student, &exams, false);
+    let assignment_percent = assignment_component(student, course_id);
+
+    let components = [
+        (quiz_percent, config.weights.quizzes),
+        (exam_percent, config.weights.exams),
+        (assignment_percent, config.weights.assignments),
+    ];
+    let total_weight: u32 = components
+        .iter()
+        .filter(|(percent, _)| percent.is_some())
+        .map(|(_, weight)| weight)
+        .sum();
+    if total_weight == 0 {
+        return Err("Course has no weighted graded work".to_string());
+    }
+
+    let weighted: f64 = components
+        .iter()
+        .filter_map(|(percent, weight)| percent.map(|p| p * *weight as f64))
+        .sum();
+    let final_percent = weighted / total_weight as f64;
+
+    let letter = config
+        .bands
+        .iter()
+        .find(|band| final_percent >= band.min_percent as f64)
+        .map(|band| band.letter.clone())
+        .unwrap_or_default();
+
+    Ok(FinalGrade {
+        student,
+        course_id,
+        quiz_percent,
+        exam_percent,
+        assignment_percent,
+        final_percent,
+        letter,
+        passed: final_percent >= config.passing_percent as f64,
+        computed_at: time(),
+    })
+}
+
+#[ic_cdk::query]
+fn get_final_grade(course_id: u64, student: Principal) -> Result<FinalGrade, String> {
+    let caller = ic_cdk::caller();
+    if caller != student {
+        ensure_permission(course_id, caller, StaffPermission::Grade)?;
+    }
+
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(student, course_id)))
+        .ok_or_else(|| "Student is not enrolled in this course".to_string())?;
+
+    // Completed enrollments keep the grade they were completed with.
+    match enrollment.final_grade {
+        Some(grade) => Ok(grade),
+        None => compute_final_grade(student, course_id),
+    }
+}
+
+#[ic_cdk::query]
+fn get_course_gradebook(course_id: u64) -> Result<Vec<FinalGrade>, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::Grade)?;
+
+    Ok(get_course_enrollments(course_id)
+        .into_iter()
+        .filter_map(|enrollment| match enrollment.final_grade {
+            Some(grade) => Some(grade),
+            None => compute_final_grade(enrollment.student, course_id).ok(),
+        })
+        .collect())
+}
+
+#[ic_cdk::query]
+fn export_gradebook_csv(course_id: u64) -> Result<String, String> {
+    let format_percent = |percent: Option<f64>| match percent {
+        Some(p) => format!("{:.2}", p),
+        None => String::new(),
+    };
+
+    let mut csv = String::from(
+        "student,quiz_percent,exam_percent,assignment_percent,final_percent,letter,passed\n",
+    );
+    for grade in get_course_gradebook(course_id)? {
+        csv.push_str(&format!(
+            "{},{},{},{},{:.2},{},{}\n",
+            grade.student.to_text(),
+            format_percent(grade.quiz_percent),
+            format_percent(grade.exam_percent),
+            format_percent(grade.assignment_percent),
+            grade.final_percent,
+            grade.letter.replace(',', " "),
+            grade.passed
+        ));
+    }
+
+    Ok(csv)
+}
+
+// Prerequisites
+fn has_completed_course(student: Principal, course_id: u64) -> bool {
+    ENROLLMENT_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .get(&(student, course_id))
+            .map(|enrollment| enrollment.completed)
+            .unwrap_or(false)
+    })
+}
+
+// Rejects unknown or duplicate prerequisites and any list that would make
+// `course_id` reachable from its own prerequisites.
+fn validate_prerequisites(course_id: u64, prerequisites: &[u64]) -> Result<(), String> {
+    let mut seen: Vec<u64> = Vec::new();
+    for &prerequisite in prerequisites {
+        if prerequisite == course_id {
+            return Err("A course cannot be its own prerequisite".to_string());
+        }
+        if seen.contains(&prerequisite) {
+            return Err(format!("Prerequisite course {} is listed twice", prerequisite));
+        }
+        let exists = COURSE_STORAGE.with(|storage| storage.borrow().contains_key(&prerequisite));
+        if !exists {
+            return Err(format!("Prerequisite course {} not found", prerequisite));
+        }
+        seen.push(prerequisite);
+    }
+
+    let mut stack: Vec<u64> = prerequisites.to_vec();
+    let mut visited: Vec<u64> = Vec::new();
+    while let Some(current) = stack.pop() {
+        if current == course_id {
+            return Err("Prerequisites would create a cycle".to_string());
+        }
+        if visited.contains(&current) {
+            continue;
+        }
+        visited.push(current);
+        if let Some(course) = COURSE_STORAGE.with(|storage| storage.borrow().get(&current)) {
+            stack.extend(course.prerequisites);
+        }
+    }
+
+    Ok(())
+}
+
+fn check_prerequisites_completed(student: Principal, course: &Course) -> Result<(), String> {
+    let missing: Vec<String> = course
+        .prerequisites
+        .iter()
+        .filter(|&&prerequisite| !has_completed_course(student, prerequisite))
+        .map(|&prerequisite| {
+            COURSE_STORAGE
+                .with(|storage| storage.borrow().get(&prerequisite))
+                .map(|c| c.title)
+                .unwrap_or_else(|| prerequisite.to_string())
+        })
+        .collect();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Complete the prerequisite courses first: {}", missing.join(", ")))
+    }
+}
+
+fn build_prerequisite_node(student: Principal, course: Course) -> PrerequisiteNode {
+    let enrollment = ENROLLMENT_STORAGE.with(|storage| storage.borrow().get(&(student, course.id)));
+    let prerequisites = course
+        .prerequisites
+        .iter()
+        .filter_map(|id| COURSE_STORAGE.with(|storage| storage.borrow().get(id)))
+        .map(|prerequisite| build_prerequisite_node(student, prerequisite))
+        .collect();
+
+    PrerequisiteNode {
+        course_id: course.id,
+        title: course.title,
+        enrolled: enrollment.is_some(),
+        completed: enrollment.map(|e| e.completed).unwrap_or(false),
+        prerequisites,
+    }
+}
+
+#[ic_cdk::query]
+fn get_prerequisite_tree(course_id: u64) -> Result<PrerequisiteNode, String> {
+    let caller = ic_cdk::caller();
+
+    match COURSE_STORAGE.with(|storage| storage.borrow().get(&course_id)) {
+        Some(course) => Ok(build_prerequisite_node(caller, course)),
+        None => Err("Course not found".to_string()),
+    }
+}
+
+// Learning Paths
+fn validate_path_steps(steps: &[PathStep], required_electives: u32) -> Result<(), String> {
+    if steps.is_empty() {
+        return Err("A learning path needs at least one course".to_string());
+    }
+    for (index, step) in steps.iter().enumerate() {
+        if steps[..index].iter().any(|s| s.course_id == step.course_id) {
+            return Err(format!("Course {} appears more than once in the path", step.course_id));
+        }
+        let exists = COURSE_STORAGE.with(|storage| storage.borrow().contains_key(&step.course_id));
+        if !exists {
+            return Err(format!("Course {} not found", step.course_id));
+        }
+    }
+    let electives = steps.iter().filter(|s| s.elective).count() as u32;
+    if required_electives > electives {
+        return Err("Required electives exceed the number of elective courses".to_string());
+    }
+    if electives == steps.len() as u32 && required_electives == 0 {
+        return Err("A learning path must require at least one course".to_string());
+    }
+    Ok(())
+}
+
+// Bonuses are minted by token_rewards, which also caps them, so only admins
+// can attach one to a path.
+fn validate_path_bonus(caller: Principal, completion_bonus: u64) -> Result<(), String> {
+    if completion_bonus > 0 && !is_admin(caller) {
+        return Err("Only admins can set a completion bonus".to_string());
+    }
+    Ok(())
+}
+
+#[ic_cdk::update]
+fn create_learning_path(payload: CreateLearningPathPayload) -> Result<LearningPath, String> {
+    let caller = ic_cdk::caller();
+
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot create learning paths".to_string());
+    }
+
+    let is_educator = !educator_course_ids(caller).is_empty()
+        || STAFF_STORAGE.with(|storage| {
+            storage.borrow().iter().any(|((_, member), staff)| {
+                member == caller && staff.permissions.contains(&StaffPermission::EditContent)
+            })
+        });
+    if !is_educator && !is_admin(caller) {
+        return Err("Only educators or admins can create learning paths".to_string());
+    }
+
+    if payload.title.trim().is_empty() {
+        return Err("Learning path title cannot be empty".to_string());
+    }
+    validate_path_steps(&payload.steps, payload.required_electives)?;
+    validate_path_bonus(caller, payload.completion_bonus)?;
+
+    let path_id = get_next_id();
+    let path = LearningPath {
+        id: path_id,
+        title: payload.title,
+        description: payload.description,
+        creator: caller,
+        steps: payload.steps,
+        required_electives: payload.required_electives,
+        completion_bonus: payload.completion_bonus,
+        created_at: time(),
+        updated_at: time(),
+    };
+
+    LEARNING_PATH_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(path_id, path.clone());
+    });
+
+    Ok(path)
+}
+
+#[ic_cdk::update]
+fn update_learning_path(path_id: u64, payload: UpdateLearningPathPayload) -> Result<LearningPath, String> {
+    let caller = ic_cdk::caller();
+
+    let mut path = LEARNING_PATH_STORAGE
+        .with(|storage| storage.borrow().get(&path_id))
+        .ok_or_else(|| "Learning path not found".to_string())?;
+
+    if path.creator != caller && !is_admin(caller) {
+        return Err("Only the path creator or an admin can update this learning path".to_string());
+    }
+
+    if let Some(title) = payload.title {
+        if title.trim().is_empty() {
+            return Err("Learning path title cannot be empty".to_string());
+        }
+        path.title = title;
+    }
+    if let Some(description) = payload.description {
+        path.description = description;
+    }
+    if let Some(steps) = payload.steps {
+        path.steps = steps;
+    }
+    if let Some(required_electives) = payload.required_electives {
+        path.required_electives = required_electives;
+    }
+    if let Some(completion_bonus) = payload.completion_bonus {
+        validate_path_bonus(caller, completion_bonus)?;
+        path.completion_bonus = completion_bonus;
+    }
+    validate_path_steps(&path.steps, path.required_electives)?;
+    path.updated_at = time();
+
+    LEARNING_PATH_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(path_id, path.clone());
+    });
+
+    Ok(path)
+}
+
+#[ic_cdk::query]
+fn get_learning_path(path_id: u64) -> Option<LearningPath> {
+    LEARNING_PATH_STORAGE.with(|storage| storage.borrow().get(&path_id))
+}
+
+#[ic_cdk::query]
+fn get_all_learning_paths() -> Vec<LearningPath> {
+    LEARNING_PATH_STORAGE.with(|storage| {
+        storage.borrow().iter().map(|(_, path)| path).collect()
+    })
+}
+
+fn compute_path_progress(student: Principal, path: &LearningPath) -> PathProgress {
+    let steps: Vec<PathStepProgress> = path
+        .steps
+        .iter()
+        .map(|step| {
+            let enrollment = ENROLLMENT_STORAGE.with(|storage| {
+                storage.borrow().get(&(student, step.course_id))
+            });
+            PathStepProgress {
+                course_id: step.course_id,
+                title: COURSE_STORAGE
+                    .with(|storage| storage.borrow().get(&step.course_id))
+                    .map(|course| course.title)
+                    .unwrap_or_default(),
+                elective: step.elective,
+                enrolled: enrollment.is_some(),
+                completed: enrollment.map(|e| e.completed).unwrap_or(false),
+            }
+        })
+        .collect();
+
+    let required_total = steps.iter().filter(|s| !s.elective).count() as u32;
+    let required_completed = steps.iter().filter(|s| !s.elective && s.completed).count() as u32;
+    let electives_completed = steps.iter().filter(|s| s.elective && s.completed).count() as u32;
+
+    let counted = required_completed + electives_completed.min(path.required_electives);
+    let needed = required_total + path.required_electives;
+    let percent_complete = if needed == 0 {
+        100.0
+    } else {
+        counted as f64 * 100.0 / needed as f64
+    };
+
+    let electives_outstanding = electives_completed < path.required_electives;
+    let next_course_id = steps
+        .iter()
+        .find(|s| !s.completed && (!s.elective || electives_outstanding))
+        .map(|s| s.course_id);
+
+    PathProgress {
+        path_id: path.id,
+        title: path.title.clone(),
+        steps,
+        required_completed,
+        required_total,
+        electives_completed,
+        electives_required: path.required_electives,
+        percent_complete,
+        next_course_id,
+        completed: counted == needed,
+    }
+}
+
+// Marks the enrollment complete and records a completion event if the
+// learner has met every requirement of the path.
+fn complete_path_if_finished(mut enrollment: PathEnrollment, path: &LearningPath) -> PathEnrollment {
+    if enrollment.completed || !compute_path_progress(enrollment.student, path).completed {
+        return enrollment;
+    }
+
+    let now = time();
+    enrollment.completed = true;
+    enrollment.completion_date = Some(now);
+    PATH_ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((enrollment.student, path.id), enrollment.clone());
+    });
+
+    let event = PathCompletionEvent {
+        id: get_next_id(),
+        path_id: path.id,
+        student: enrollment.student,
+        completed_at: now,
+        completion_bonus: path.completion_bonus,
+        certificate_id: None,
+        bonus_transaction_id: None,
+    };
+    PATH_COMPLETION_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((event.student, event.path_id), event);
+    });
+
+    queue_completion_dispatch(enrollment.student, CompletionKind::LearningPath, path.id);
+    schedule_completion_dispatch(enrollment.student, path.id, 0);
+
+    enrollment
+}
+
+fn path_completion_event(student: Principal, path_id: u64) -> Option<PathCompletionEvent> {
+    PATH_COMPLETION_STORAGE.with(|storage| storage.borrow().get(&(student, path_id)))
+}
+
+fn record_path_completions(student: Principal, course_id: u64) {
+    let enrollments: Vec<PathEnrollment> = PATH_ENROLLMENT_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((student, 0)..=(student, u64::MAX))
+            .map(|(_, enrollment)| enrollment)
+            .filter(|enrollment| !enrollment.completed)
+            .collect()
+    });
+
+    for enrollment in enrollments {
+        if let Some(path) = get_learning_path(enrollment.path_id) {
+            if path.steps.iter().any(|step| step.course_id == course_id) {
+                complete_path_if_finished(enrollment, &path);
+            }
+        }
+    }
+}
+
+#[ic_cdk::update]
+fn enroll_in_learning_path(path_id: u64) -> Result<PathEnrollment, String> {
+    let caller = ic_cdk::caller();
+
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot enroll in learning paths".to_string());
+    }
+
+    let path = get_learning_path(path_id).ok_or_else(|| "Learning path not found".to_string())?;
+
+    let enrollment_key = (caller, path_id);
+    let already_enrolled = PATH_ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&enrollment_key)
+    });
+
+    if already_enrolled {
+        return Err("Already enrolled in this learning path".to_string());
+    }
+
+    let enrollment = PathEnrollment {
+        student: caller,
+        path_id,
+        enrolled_at: time(),
+        completed: false,
+        completion_date: None,
+    };
+
+    PATH_ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(enrollment_key, enrollment.clone());
+    });
+
+    // Courses finished before joining the path still count towards it.
+    Ok(complete_path_if_finished(enrollment, &path))
+}
+
+#[ic_cdk::query]
+fn get_learning_path_progress(path_id: u64) -> Result<PathProgress, String> {
+    let caller = ic_cdk::caller();
+
+    let path = get_learning_path(path_id).ok_or_else(|| "Learning path not found".to_string())?;
+    Ok(compute_path_progress(caller, &path))
+}
+
+#[ic_cdk::query]
+fn get_my_learning_paths() -> Vec<PathEnrollment> {
+    let caller = ic_cdk::caller();
+
+    PATH_ENROLLMENT_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((caller, 0)..=(caller, u64::MAX))
+            .map(|(_, enrollment)| enrollment)
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_path_completion_events(path_id: u64) -> Result<Vec<PathCompletionEvent>, String> {
+    let caller = ic_cdk::caller();
+
+    let path = get_learning_path(path_id).ok_or_else(|| "Learning path not found".to_string())?;
+    if path.creator != caller && !is_admin(caller) {
+        return Err("Only the path creator or an admin can view completion events".to_string());
+    }
+
+    Ok(PATH_COMPLETION_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, event)| {
+                if event.path_id == path_id {
+                    Some(event)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }))
+}
+
+// Search
+const STOP_WORDS: &[&str] = &[
+    "a", "about", "an", "and", "are", "as", "at", "be", "by", "for", "from", "how", "in", "into",
+    "is", "it", "its", "of", "on", "or", "that", "the", "this", "to", "was", "what", "with", "you",
+    "your",
+];
+
+// Field weights used when building postings.
+const TITLE_WEIGHT: u32 = 5;
+const TAG_WEIGHT: u32 = 4;
+const LESSON_TITLE_WEIGHT: u32 = 2;
+const DESCRIPTION_WEIGHT: u32 = 1;
+
+// Light suffix-stripping stemmer; it only needs to map the common English
+// inflections of a word onto the same index term.
+fn stem(word: &str) -> String {
+    const SUFFIXES: &[(&str, &str)] = &[
+        ("ational", "ate"),
+        ("ization", "ize"),
+        ("fulness", "ful"),
+        ("ousness", "ous"),
+        ("iveness", "ive"),
+        ("ations", "ate"),
+        ("ation", "ate"),
+        ("ments", ""),
+        ("ment", ""),
+        ("ness", ""),
+        ("ings", ""),
+        ("ing", ""),
+        ("ies", "y"),
+        ("ied", "y"),
+        ("ers", ""),
+        ("er", ""),
+        ("ed", ""),
+        ("ly", ""),
+        ("s", ""),
+    ];
+
+    if word.ends_with("ss") || word.ends_with("us") || word.ends_with("is") {
+        return word.to_string();
+    }
+    let mut stemmed = word.to_string();
+    for (suffix, replacement) in SUFFIXES {
+        if let Some(root) = word.strip_suffix(suffix) {
+            if root.chars().count() >= 3 {
+                stemmed = format!("{}{}", root, replacement);
+                break;
+            }
+        }
+    }
+    // "course" and "courses" should both end up as "cours".
+    match stemmed.strip_suffix('e') {
+        Some(root) if root.chars().count() >= 3 => root.to_string(),
+        _ => stemmed,
+    }
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(|token| token.to_lowercase())
+        .filter(|token| token.chars().count() > 1 && !STOP_WORDS.contains(&token.as_str()))
+        .map(|token| stem(&token))
+        .collect()
+}
+
+fn course_terms(course: &Course) -> Vec<(String, u32)> {
+    let mut terms: Vec<(String, u32)> = Vec::new();
+    let mut add = |text: &str, weight: u32| {
+        for token in tokenize(text) {
+            match terms.iter_mut().find(|(term, _)| *term == token) {
+                Some((_, score)) => *score += weight,
+                None => terms.push((token, weight)),
+            }
+        }
+    };
+
+    add(&course.title, TITLE_WEIGHT);
+    add(&course.description, DESCRIPTION_WEIGHT);
+    for tag in &course.tags {
+        add(tag, TAG_WEIGHT);
+    }
+    for lesson in &course.lessons {
+        add(&lesson.title, LESSON_TITLE_WEIGHT);
+    }
+    for translation in course_translations(course.id) {
+        add(&translation.title, TITLE_WEIGHT);
+        add(&translation.description, DESCRIPTION_WEIGHT);
+        for lesson in &translation.lessons {
+            add(&lesson.title, LESSON_TITLE_WEIGHT);
+        }
+    }
+    terms
+}
+
+fn index_course(course: &Course) {
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for (term, score) in course_terms(course) {
+            let mut entry = index.get(&term).unwrap_or_default();
+            entry.postings.retain(|(course_id, _)| *course_id != course.id);
+            entry.postings.push((course.id, score));
+            index.insert(term, entry);
+        }
+    });
+}
+
+fn unindex_course(course: &Course) {
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for (term, _) in course_terms(course) {
+            if let Some(mut entry) = index.get(&term) {
+                entry.postings.retain(|(course_id, _)| *course_id != course.id);
+                if entry.postings.is_empty() {
+                    index.remove(&term);
+                } else {
+                    index.insert(term, entry);
+                }
+            }
+        }
+    });
+}
+
+fn term_postings(term: &str) -> Vec<(u64, u32)> {
+    SEARCH_INDEX.with(|index| {
+        index
+            .borrow()
+            .get(&term.to_string())
+            .map(|entry| entry.postings)
+            .unwrap_or_default()
+    })
+}
+
+fn course_has_tags(course: &Course, tags: &[String]) -> bool {
+    tags.iter()
+        .map(|tag| normalize_tag(tag))
+        .all(|tag| course.tags.contains(&tag))
+}
+
+fn page_limit(limit: u32) -> usize {
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        MAX_PAGE_SIZE as usize
+    } else {
+        limit as usize
+    }
+}
+
+#[ic_cdk::query]
+fn search_courses(query: SearchQuery) -> SearchResults {
+    let total_courses = COURSE_STORAGE.with(|storage| storage.borrow().len()) as f64;
+
+    // Sum of tf-idf contributions per course across all query terms.
+    let mut scores: Vec<(u64, f64)> = Vec::new();
+    let mut terms = tokenize(&query.text);
+    terms.sort();
+    terms.dedup();
+    for term in &terms {
+        let postings = term_postings(term);
+        if postings.is_empty() {
+            continue;
+        }
+        let idf = (1.0 + total_courses / postings.len() as f64).ln();
+        for (course_id, weight) in postings {
+            let contribution = weight as f64 * idf;
+            match scores.iter_mut().find(|(id, _)| *id == course_id) {
+                Some((_, score)) => *score += contribution,
+                None => scores.push((course_id, contribution)),
+            }
+        }
+    }
+
+    let mut hits: Vec<SearchHit> = if terms.is_empty() {
+        get_all_courses()
+            .into_iter()
+            .map(|course| SearchHit { course, score: 0.0 })
+            .collect()
+    } else {
+        scores
+            .into_iter()
+            .filter_map(|(course_id, score)| {
+                get_course(course_id).map(|course| SearchHit {
+                    course: public_course_view(course, ic_cdk::caller()),
+                    score,
+                })
+            })
+            .collect()
+    };
+
+    hits.retain(|hit| {
+        if !hit.course.published {
+            return false;
+        }
+        let level_matches = match &query.level {
+            Some(level) => hit.course.level == *level,
+            None => true,
+        };
+        level_matches && course_has_tags(&hit.course, &query.tags)
+    });
+    hits.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(b.course.created_at.cmp(&a.course.created_at))
+    });
+
+    SearchResults {
+        total: hits.len() as u64,
+        hits: hits
+            .into_iter()
+            .skip(query.offset as usize)
+            .take(page_limit(query.limit))
+            .map(|hit| SearchHit {
+                course: localize_course(hit.course, query.locale.as_deref()),
+                score: hit.score,
+            })
+            .collect(),
+    }
+}
+
+#[ic_cdk::update]
+fn rebuild_catalog_indexes() -> Result<u64, String> {
+    let caller = ic_cdk::caller();
+
+    if !is_admin(caller) {
+        return Err("Only admins can rebuild the catalog indexes".to_string());
+    }
+
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let keys: Vec<String> = index.iter().map(|(key, _)| key).collect();
+        for key in keys {
+            index.remove(&key);
+        }
+    });
+    TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let keys: Vec<String> = index.iter().map(|(key, _)| key).collect();
+        for key in keys {
+            index.remove(&key);
+        }
+    });
+    LEVEL_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let keys: Vec<(u8, u64)> = index.iter().map(|(key, _)| key).collect();
+        for key in keys {
+            index.remove(&key);
+        }
+    });
+
+    let courses: Vec<Course> = COURSE_STORAGE.with(|storage| {
+        storage.borrow().iter().map(|(_, course)| course).collect()
+    });
+    for course in &courses {
+        add_to_catalog(course);
+    }
+
+    Ok(courses.len() as u64)
+}
+
+// Catalog Facets
+// Lowercases, joins words with '-', and drops punctuation other than the
+// characters used in names like "c++", "c#" or "node.js".
+fn normalize_tag(tag: &str) -> String {
+    tag.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || matches!(c, '+' | '#' | '.' | '-'))
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().map(|tag| normalize_tag(tag)) {
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+fn level_key(level: &CourseLevel) -> u8 {
+    match level {
+        CourseLevel::Beginner => 0,
+        CourseLevel::Intermediate => 1,
+        CourseLevel::Advanced => 2,
+    }
+}
+
+const ALL_LEVELS: [CourseLevel; 3] = [
+    CourseLevel::Beginner,
+    CourseLevel::Intermediate,
+    CourseLevel::Advanced,
+];
+
+// Keeps the search index and, for published courses, the facet indexes in
+// step with a course record. Pair with `remove_from_catalog` on updates.
+fn add_to_catalog(course: &Course) {
+    index_course(course);
+    if !course.published {
+        return;
+    }
+    TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for tag in &course.tags {
+            let mut entry = index.get(tag).unwrap_or_default();
+            if !entry.course_ids.contains(&course.id) {
+                entry.course_ids.push(course.id);
+                index.insert(tag.clone(), entry);
+            }
+        }
+    });
+    LEVEL_INDEX.with(|index| {
+        index.borrow_mut().insert((level_key(&course.level), course.id), ());
+    });
+}
+
+fn remove_from_catalog(course: &Course) {
+    unindex_course(course);
+    TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for tag in &course.tags {
+            if let Some(mut entry) = index.get(tag) {
+                entry.course_ids.retain(|course_id| *course_id != course.id);
+                if entry.course_ids.is_empty() {
+                    index.remove(tag);
+                } else {
+                    index.insert(tag.clone(), entry);
+                }
+            }
+        }
+    });
+    LEVEL_INDEX.with(|index| {
+        index.borrow_mut().remove(&(level_key(&course.level), course.id));
+    });
+}
+
+fn courses_with_tag(tag: &str) -> Vec<u64> {
+    TAG_INDEX.with(|index| {
+        index
+            .borrow()
+            .get(&tag.to_string())
+            .map(|entry| entry.course_ids)
+            .unwrap_or_default()
+    })
+}
+
+fn courses_with_level(level: &CourseLevel) -> Vec<u64> {
+    let key = level_key(level);
+    LEVEL_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((key, 0)..=(key, u64::MAX))
+            .map(|((_, course_id), _)| course_id)
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_catalog_facets() -> CatalogFacets {
+    let mut tags: Vec<TagFacetCount> = TAG_INDEX.with(|index| {
+        index
+            .borrow()
+            .iter()
+            .map(|(tag, entry)| TagFacetCount {
+                tag,
+                count: entry.course_ids.len() as u64,
+            })
+            .collect()
+    });
+    tags.sort_by(|a, b| b.count.cmp(&a.count).then(a.tag.cmp(&b.tag)));
+
+    let levels: Vec<LevelFacetCount> = ALL_LEVELS
+        .iter()
+        .map(|level| LevelFacetCount {
+            level: level.clone(),
+            count: courses_with_level(level).len() as u64,
+        })
+        .collect();
+
+    CatalogFacets {
+        total_published: levels.iter().map(|l| l.count).sum(),
+        tags,
+        levels,
+    }
+}
+
+#[ic_cdk::query]
+fn browse_catalog(filter: CatalogFilter) -> CoursePage {
+    let caller = ic_cdk::caller();
+    let levels: Vec<CourseLevel> = if filter.levels.is_empty() {
+        ALL_LEVELS.to_vec()
+    } else {
+        filter.levels.clone()
+    };
+
+    // Every published course appears in exactly one level bucket, so the
+    // level postings are the starting candidate set.
+    let mut course_ids: Vec<u64> = levels.iter().flat_map(courses_with_level).collect();
+    for tag in filter.tags.iter().map(|tag| normalize_tag(tag)) {
+        let tagged = courses_with_tag(&tag);
+        course_ids.retain(|id| tagged.contains(id));
+    }
+    course_ids.sort_by(|a, b| b.cmp(a));
+    course_ids.dedup();
+
+    CoursePage {
+        total: course_ids.len() as u64,
+        courses: course_ids
+            .into_iter()
+            .skip(filter.offset as usize)
+            .take(page_limit(filter.limit))
+            .filter_map(get_course)
+            .map(|course| public_course_view(localize_course(course, filter.locale.as_deref()), caller))
+            .collect(),
+    }
+}
+
+// Reviews
+fn validate_review(payload: &ReviewPayload) -> Result<(), String> {
+    if payload.rating < 1 || payload.rating > 5 {
+        return Err("Rating must be between 1 and 5".to_string());
+    }
+    if payload.text.chars().count() > 5000 {
+        return Err("Review text is too long".to_string());
+    }
+    Ok(())
+}
+
+// Applies a rating change to the course aggregate without rescanning reviews.
+fn adjust_course_rating(course_id: u64, added: Option<u8>, removed: Option<u8>) {
+    COURSE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(mut course) = storage.get(&course_id) {
+            if let Some(rating) = removed {
+                course.rating_total = course.rating_total.saturating_sub(rating as u64);
+                course.rating_count = course.rating_count.saturating_sub(1);
+            }
+            if let Some(rating) = added {
+                course.rating_total += rating as u64;
+                course.rating_count += 1;
+            }
+            course.average_rating = if course.rating_count == 0 {
+                0.0
+            } else {
+                course.rating_total as f64 / course.rating_count as f64
+            };
+            storage.insert(course_id, course);
+        }
+    });
+}
+
+#[ic_cdk::update]
+fn submit_review(course_id: u64, payload: ReviewPayload) -> Result<Review, String> {
+    let caller = ic_cdk::caller();
+    validate_review(&payload)?;
+
+    let course = get_course(course_id).ok_or_else(|| "Course not found".to_string())?;
+    if course.educator == caller || staff_member(course_id, caller).is_some() {
+        return Err("Course staff cannot review their own course".to_string());
+    }
+
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(caller, course_id)))
+        .ok_or_else(|| "Only enrolled learners can review this course".to_string())?;
+    if course.reviews_require_completion && !enrollment.completed {
+        return Err("Complete the course before reviewing it".to_string());
+    }
+
+    let review_key = (course_id, caller);
+    let already_reviewed = REVIEW_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&review_key)
+    });
+
+    if already_reviewed {
+        return Err("You have already reviewed this course".to_string());
+    }
+
+    let review = Review {
+        course_id,
+        reviewer: caller,
+        rating: payload.rating,
+        text: payload.text,
+        response: None,
+        created_at: time(),
+        updated_at: time(),
+    };
+
+    REVIEW_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(review_key, review.clone());
+    });
+    adjust_course_rating(course_id, Some(review.rating), None);
+
+    Ok(review)
+}
+
+#[ic_cdk::update]
+fn update_review(course_id: u64, payload: ReviewPayload) -> Result<Review, String> {
+    let caller = ic_cdk::caller();
+    validate_review(&payload)?;
+
+    let review_key = (course_id, caller);
+    let mut review = REVIEW_STORAGE
+        .with(|storage| storage.borrow().get(&review_key))
+        .ok_or_else(|| "Review not found".to_string())?;
+
+    let previous_rating = review.rating;
+    review.rating = payload.rating;
+    review.text = payload.text;
+    review.updated_at = time();
+
+    REVIEW_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(review_key, review.clone());
+    });
+    adjust_course_rating(course_id, Some(review.rating), Some(previous_rating));
+
+    Ok(review)
+}
+
+#[ic_cdk::update]
+fn delete_review(course_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+
+    match REVIEW_STORAGE.with(|storage| storage.borrow_mut().remove(&(course_id, caller))) {
+        Some(review) => {
+            adjust_course_rating(course_id, None, Some(review.rating));
+            Ok("Review deleted successfully".to_string())
+        }
+        None => Err("Review not found".to_string()),
+    }
+}
+
+#[ic_cdk::update]
+fn respond_to_review(course_id: u64, reviewer: Principal, text: String) -> Result<Review, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    if text.trim().is_empty() {
+        return Err("Response cannot be empty".to_string());
+    }
+
+    let review_key = (course_id, reviewer);
+    REVIEW_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        match storage.get(&review_key) {
+            Some(mut review) => {
+                review.response = Some(ReviewResponse {
+                    responder: caller,
+                    text,
+                    responded_at: time(),
+                });
+                storage.insert(review_key, review.clone());
+                Ok(review)
+            }
+            None => Err("Review not found".to_string()),
+        }
+    })
+}
+
+#[ic_cdk::query]
+fn get_course_reviews(course_id: u64, offset: u32, limit: u32) -> ReviewPage {
+    REVIEW_STORAGE.with(|storage| {
+        let storage = storage.borrow();
+        // The management canister id is the empty principal, which sorts first.
+        let mut reviews: Vec<Review> = storage
+            .range((course_id, Principal::management_canister())..)
+            .take_while(|((c_id, _), _)| *c_id == course_id)
+            .map(|(_, review)| review)
+            .collect();
+        reviews.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+
+        ReviewPage {
+            total: reviews.len() as u64,
+            reviews: reviews
+                .into_iter()
+                .skip(offset as usize)
+                .take(page_limit(limit))
+                .collect(),
+        }
+    })
+}
+
+#[ic_cdk::query]
+fn get_my_review(course_id: u64) -> Option<Review> {
+    let caller = ic_cdk::caller();
+    REVIEW_STORAGE.with(|storage| storage.borrow().get(&(course_id, caller)))
+}
+
+// Cohorts
+fn validate_cohort(course: &Course, cohort: &Cohort) -> Result<(), String> {
+    if cohort.name.trim().is_empty() {
+        return Err("Cohort name cannot be empty".to_string());
+    }
+    if cohort.starts_at >= cohort.ends_at {
+        return Err("Cohort must start before it ends".to_string());
+    }
+    if cohort.enrollment_opens_at >= cohort.enrollment_closes_at {
+        return Err("Enrollment window must open before it closes".to_string());
+    }
+    if cohort.enrollment_closes_at > cohort.ends_at {
+        return Err("Enrollment cannot close after the cohort ends".to_string());
+    }
+    if cohort.max_seats == 0 {
+        return Err("A cohort needs at least one seat".to_string());
+    }
+    for deadline in &cohort.deadlines {
+        if !course.lessons.iter().any(|lesson| lesson.id == deadline.lesson_id) {
+            return Err(format!("Deadline refers to unknown lesson {}", deadline.lesson_id));
+        }
+    }
+    Ok(())
+}
+
+fn course_has_cohorts(course_id: u64) -> bool {
+    COHORT_STORAGE.with(|storage| {
+        storage.borrow().iter().any(|(_, cohort)| cohort.course_id == course_id)
+    })
+}
+
+#[ic_cdk::update]
+fn create_cohort(payload: CreateCohortPayload) -> Result<Cohort, String> {
+    let caller = ic_cdk::caller();
+    let course =
+        ensure_permission(payload.course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    let cohort = Cohort {
+        id: get_next_id(),
+        course_id: payload.course_id,
+        name: payload.name,
+        starts_at: payload.starts_at,
+        ends_at: payload.ends_at,
+        enrollment_opens_at: payload.enrollment_opens_at,
+        enrollment_closes_at: payload.enrollment_closes_at,
+        max_seats: payload.max_seats,
+        enrolled_count: 0,
+        deadlines: payload.deadlines,
+        created_at: time(),
+        updated_at: time(),
+    };
+    validate_cohort(&course, &cohort)?;
+
+    COHORT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(cohort.id, cohort.clone());
+    });
+
+    Ok(cohort)
+}
+
+#[ic_cdk::update]
+fn update_cohort(cohort_id: u64, payload: UpdateCohortPayload) -> Result<Cohort, String> {
+    let caller = ic_cdk::caller();
+
+    let mut cohort = get_cohort(cohort_id).ok_or_else(|| "Cohort not found".to_string())?;
+    let course =
+        ensure_permission(cohort.course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    if let Some(name) = payload.name {
+        cohort.name = name;
+    }
+    if let Some(starts_at) = payload.starts_at {
+        cohort.starts_at = starts_at;
+    }
+    if let Some(ends_at) = payload.ends_at {
+        cohort.ends_at = ends_at;
+    }
+    if let Some(enrollment_opens_at) = payload.enrollment_opens_at {
+        cohort.enrollment_opens_at = enrollment_opens_at;
+    }
+    if let Some(enrollment_closes_at) = payload.enrollment_closes_at {
+        cohort.enrollment_closes_at = enrollment_closes_at;
+    }
+    if let Some(max_seats) = payload.max_seats {
+        if max_seats < cohort.enrolled_count {
+            return Err("Max seats cannot be lower than the current enrollment".to_string());
+        }
+        cohort.max_seats = max_seats;
+    }
+    if let Some(deadlines) = payload.deadlines {
+        cohort.deadlines = deadlines;
+    }
+    validate_cohort(&course, &cohort)?;
+    cohort.updated_at = time();
+
+    COHORT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(cohort_id, cohort.clone());
+    });
+
+    // Extra seats go to the waitlist first.
+    promote_from_waitlist(cohort_id);
+
+    Ok(get_cohort(cohort_id).unwrap_or(cohort))
+}
+
+#[ic_cdk::query]
+fn get_cohort(cohort_id: u64) -> Option<Cohort> {
+    COHORT_STORAGE.with(|storage| storage.borrow().get(&cohort_id))
+}
+
+#[ic_cdk::query]
+fn get_course_cohorts(course_id: u64) -> Vec<Cohort> {
+    COHORT_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, cohort)| {
+                if cohort.course_id == course_id {
+                    Some(cohort)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+fn cohort_waitlist(cohort_id: u64) -> Vec<((u64, u64), WaitlistEntry)> {
+    WAITLIST_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((cohort_id, 0)..=(cohort_id, u64::MAX))
+            .collect()
+    })
+}
+
+fn adjust_cohort_seats(cohort_id: u64, delta: i32) {
+    COHORT_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(mut cohort) = storage.get(&cohort_id) {
+            cohort.enrolled_count = cohort.enrolled_count.saturating_add_signed(delta);
+            storage.insert(cohort_id, cohort);
+        }
+    });
+}
+
+// Fills free seats from the front of the waitlist. Learners who meanwhile
+// enrolled in the course some other way are dropped from the list.
+//
+// Seats in paid cohorts have to be paid for, so they are offered to the
+// front of the list instead and claimed through `enroll_in_cohort`.
+fn promote_from_waitlist(cohort_id: u64) -> Vec<Enrollment> {
+    let mut promoted = Vec::new();
+
+    let is_paid = get_cohort(cohort_id)
+        .and_then(|cohort| get_course(cohort.course_id))
+        .is_some_and(|course| course.price > 0);
+    if is_paid {
+        offer_waitlist_seats(cohort_id);
+        return promoted;
+    }
+
+    for (key, entry) in cohort_waitlist(cohort_id) {
+        let cohort = match get_cohort(cohort_id) {
+            Some(cohort) => cohort,
+            None => break,
+        };
+        if cohort.enrolled_count >= cohort.max_seats {
+            break;
+        }
+
+        WAITLIST_STORAGE.with(|storage| {
+            storage.borrow_mut().remove(&key);
+        });
+
+        let already_enrolled = ENROLLMENT_STORAGE.with(|storage| {
+            storage.borrow().contains_key(&(entry.student, cohort.course_id))
+        });
+        if already_enrolled {
+            continue;
+        }
+
+        adjust_cohort_seats(cohort_id, 1);
+        promoted.push(record_enrollment(entry.student, cohort.course_id, Some(cohort_id)));
+    }
+
+    promoted
+}
+
+// Holds each free seat of a paid cohort for a learner at the front of the
+// waitlist and notifies them. Offers not taken up within
+// SEAT_OFFER_WINDOW_SECS lapse and the learner leaves the list, passing the
+// seat on to the next in line.
+fn offer_waitlist_seats(cohort_id: u64) {
+    let cohort = match get_cohort(cohort_id) {
+        Some(cohort) => cohort,
+        None => return,
+    };
+    let now = time();
+    let window = SEAT_OFFER_WINDOW_SECS * NANOS_PER_SECOND;
+    let mut free_seats = cohort.max_seats.saturating_sub(cohort.enrolled_count);
+
+    for (key, mut entry) in cohort_waitlist(cohort_id) {
+        if free_seats == 0 {
+            break;
+        }
+
+        let already_enrolled = ENROLLMENT_STORAGE.with(|storage| {
+            storage.borrow().contains_key(&(entry.student, cohort.course_id))
+        });
+        let lapsed = entry
+            .seat_offered_at
+            .is_some_and(|offered_at| now > offered_at.saturating_add(window));
+        if already_enrolled || lapsed {
+            WAITLIST_STORAGE.with(|storage| {
+                storage.borrow_mut().remove(&key);
+            });
+            continue;
+        }
+
+        free_seats -= 1;
+        if entry.seat_offered_at.is_none() {
+            entry.seat_offered_at = Some(now);
+            WAITLIST_STORAGE.with(|storage| {
+                storage.borrow_mut().insert(key, entry.clone());
+            });
+            push_notification(
+                entry.student,
+                cohort.course_id,
+                NotificationKind::SeatOffered {
+                    cohort_id,
+                    expires_at: now.saturating_add(window),
+                },
+            );
+        }
+    }
+}
+
+#[ic_cdk::update]
+async fn enroll_in_cohort(cohort_id: u64) -> Result<CohortEnrollmentStatus, String> {
+    let caller = ic_cdk::caller();
+
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot enroll in courses".to_string());
+    }
+
+    let cohort = get_cohort(cohort_id).ok_or_else(|| "Cohort not found".to_string())?;
+    let course = get_course(cohort.course_id).ok_or_else(|| "Course not found".to_string())?;
+    ensure_open_enrollment(&course)?;
+
+    let now = time();
+    if now < cohort.enrollment_opens_at || now > cohort.enrollment_closes_at {
+        return Err("Enrollment for this cohort is not open".to_string());
+    }
+
+    check_prerequisites_completed(caller, &course)?;
+
+    let already_enrolled = ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&(caller, course.id))
+    });
+    if already_enrolled {
+        return Err("Already enrolled in this course".to_string());
+    }
+
+    // Learners already on the waitlist keep their place in the queue for
+    // any free seats; lapsed seat offers are released first.
+    if course.price > 0 {
+        offer_waitlist_seats(cohort_id);
+    }
+    let waitlist = cohort_waitlist(cohort_id);
+    let free_seats = cohort.max_seats.saturating_sub(cohort.enrolled_count) as usize;
+    let position = waitlist.iter().position(|(_, entry)| entry.student == caller);
+    let queue_ahead = position.unwrap_or(waitlist.len());
+
+    if queue_ahead < free_seats {
+        let enrollment = if course.price == 0 {
+            admit_student(caller, &course, Some(cohort_id))?
+        } else {
+            let purchase = charge_for_course(caller, &course).await?;
+            admit_paid_student(caller, &course, Some(cohort_id), purchase).await?
+        };
+        return Ok(CohortEnrollmentStatus::Enrolled(Box::new(enrollment)));
+    }
+
+    if let Some(index) = position {
+        return Ok(CohortEnrollmentStatus::Waitlisted {
+            position: index as u32 + 1,
+        });
+    }
+
+    let entry = WaitlistEntry {
+        cohort_id,
+        student: caller,
+        joined_at: now,
+        seat_offered_at: None,
+    };
+    WAITLIST_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((cohort_id, get_next_id()), entry);
+    });
+
+    Ok(CohortEnrollmentStatus::Waitlisted {
+        position: waitlist.len() as u32 + 1,
+    })
+}
+
+#[ic_cdk::update]
+fn leave_waitlist(cohort_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+
+    match cohort_waitlist(cohort_id)
+        .into_iter()
+        .find(|(_, entry)| entry.student == caller)
+    {
+        Some((key, _)) => {
+            WAITLIST_STORAGE.with(|storage| {
+                storage.borrow_mut().remove(&key);
+            });
+            Ok("Left the waitlist".to_string())
+        }
+        None => Err("Not on the waitlist for this cohort".to_string()),
+    }
+}
+
+// Kept for existing clients; `unenroll_from_course` also records the drop
+// and refunds purchased seats.
+#[ic_cdk::update]
+async fn leave_cohort(cohort_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+
+    let cohort = get_cohort(cohort_id).ok_or_else(|| "Cohort not found".to_string())?;
+    let in_cohort = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(caller, cohort.course_id)))
+        .is_some_and(|enrollment| enrollment.cohort_id == Some(cohort_id));
+    if !in_cohort {
+        return Err("Not enrolled in this cohort".to_string());
+    }
+
+    let payload = UnenrollPayload {
+        reason: None,
+        keep_progress: true,
+    };
+    match unenroll_from_course(cohort.course_id, payload).await?.refund_error {
+        Some(refund_error) => Ok(format!("Left the cohort, but the refund failed: {}", refund_error)),
+        None => Ok("Left the cohort".to_string()),
+    }
+}
+
+#[ic_cdk::query]
+fn get_cohort_waitlist(cohort_id: u64) -> Result<Vec<WaitlistEntry>, String> {
+    let caller = ic_cdk::caller();
+
+    let cohort = get_cohort(cohort_id).ok_or_else(|| "Cohort not found".to_string())?;
+    ensure_permission(cohort.course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    Ok(cohort_waitlist(cohort_id)
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .collect())
+}
+
+// Enrollment Policies
+fn ensure_open_enrollment(course: &Course) -> Result<(), String> {
+    match course.enrollment_policy {
+        EnrollmentPolicy::Open => Ok(()),
+        EnrollmentPolicy::ApprovalRequired => {
+            Err("This course requires educator approval; request enrollment instead".to_string())
+        }
+        EnrollmentPolicy::InviteOnly => Err("This course requires an invite code".to_string()),
+    }
+}
+
+// Cohort courses need a cohort with a free seat; the waitlist is only used
+// by `enroll_in_cohort`.
+fn check_admission(student: Principal, course: &Course, cohort_id: Option<u64>) -> Result<(), String> {
+    if student == Principal::anonymous() {
+        return Err("Anonymous users cannot enroll in courses".to_string());
+    }
+
+    let already_enrolled = ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&(student, course.id))
+    });
+    if already_enrolled {
+        return Err("Already enrolled in this course".to_string());
+    }
+
+    check_prerequisites_completed(student, course)?;
+
+    match cohort_id {
+        Some(cohort_id) => {
+            let cohort = get_cohort(cohort_id)
+                .filter(|cohort| cohort.course_id == course.id)
+                .ok_or_else(|| "Cohort not found for this course".to_string())?;
+            if cohort.enrolled_count >= cohort.max_seats {
+                return Err("Cohort is full".to_string());
+            }
+            Ok(())
+        }
+        None if course_has_cohorts(course.id) => {
+            Err("This course runs in cohorts; a cohort must be chosen".to_string())
+        }
+        None => Ok(()),
+    }
+}
+
+// Enrolls `student` once policy checks have passed, taking a cohort seat and
+// clearing any pending request or waitlist entry.
+fn admit_student(student: Principal, course: &Course, cohort_id: Option<u64>) -> Result<Enrollment, String> {
+    check_admission(student, course, cohort_id)?;
+
+    if let Some(cohort_id) = cohort_id {
+        adjust_cohort_seats(cohort_id, 1);
+        if let Some((key, _)) = cohort_waitlist(cohort_id)
+            .into_iter()
+            .find(|(_, entry)| entry.student == student)
+        {
+            WAITLIST_STORAGE.with(|storage| {
+                storage.borrow_mut().remove(&key);
+            });
+        }
+    }
+
+    ENROLLMENT_REQUEST_STORAGE.with(|storage| {
+        storage.borrow_mut().remove(&(course.id, student));
+    });
+
+    Ok(record_enrollment(student, course.id, cohort_id))
+}
+
+fn validate_cohort_choice(course_id: u64, cohort_id: Option<u64>) -> Result<(), String> {
+    match cohort_id {
+        Some(cohort_id) => match get_cohort(cohort_id) {
+            Some(cohort) if cohort.course_id == course_id => Ok(()),
+            _ => Err("Cohort not found for this course".to_string()),
+        },
+        None if course_has_cohorts(course_id) => {
+            Err("This course runs in cohorts; a cohort must be chosen".to_string())
+        }
+        None => Ok(()),
+    }
+}
+
+#[ic_cdk::update]
+fn request_enrollment(
+    course_id: u64,
+    cohort_id: Option<u64>,
+    message: String,
+) -> Result<EnrollmentRequest, String> {
+    let caller = ic_cdk::caller();
+
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot enroll in courses".to_string());
+    }
+
+    let course = get_course(course_id).ok_or_else(|| "Course not found".to_string())?;
+    if course.enrollment_policy != EnrollmentPolicy::ApprovalRequired {
+        return Err("This course does not take enrollment requests".to_string());
+    }
+
+    let already_enrolled = ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&(caller, course_id))
+    });
+    if already_enrolled {
+        return Err("Already enrolled in this course".to_string());
+    }
+
+    let already_requested = ENROLLMENT_REQUEST_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&(course_id, caller))
+    });
+    if already_requested {
+        return Err("An enrollment request is already pending".to_string());
+    }
+
+    validate_cohort_choice(course_id, cohort_id)?;
+    check_prerequisites_completed(caller, &course)?;
+
+    let request = EnrollmentRequest {
+        course_id,
         student: caller,
+        cohort_id,
+        message,
+        requested_at: time(),
+    };
+
+    ENROLLMENT_REQUEST_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((course_id, caller), request.clone());
+    });
+
+    Ok(request)
+}
+
+#[ic_cdk::update]
+fn cancel_enrollment_request(course_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+
+    match ENROLLMENT_REQUEST_STORAGE.with(|storage| storage.borrow_mut().remove(&(course_id, caller))) {
+        Some(_) => Ok("Enrollment request cancelled".to_string()),
+        None => Err("No pending enrollment request".to_string()),
+    }
+}
+
+#[ic_cdk::query]
+fn get_pending_enrollment_requests(course_id: u64) -> Result<Vec<EnrollmentRequest>, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    let mut requests: Vec<EnrollmentRequest> = ENROLLMENT_REQUEST_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((course_id, Principal::management_canister())..)
+            .take_while(|((id, _), _)| *id == course_id)
+            .map(|(_, request)| request)
+            .collect()
+    });
+    requests.sort_by_key(|request| request.requested_at);
+
+    Ok(requests)
+}
+
+// Approving a request for a paid course charges the requesting learner.
+#[ic_cdk::update]
+async fn approve_enrollment_request(course_id: u64, student: Principal) -> Result<Enrollment, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_permission(course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    let request = ENROLLMENT_REQUEST_STORAGE
+        .with(|storage| storage.borrow().get(&(course_id, student)))
+        .ok_or_else(|| "Enrollment request not found".to_string())?;
+
+    if course.price == 0 {
+        return admit_student(student, &course, request.cohort_id);
+    }
+
+    check_admission(student, &course, request.cohort_id)?;
+    let purchase = charge_for_course(student, &course).await?;
+    admit_paid_student(student, &course, request.cohort_id, purchase).await
+}
+
+#[ic_cdk::update]
+fn reject_enrollment_request(course_id: u64, student: Principal) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    match ENROLLMENT_REQUEST_STORAGE.with(|storage| storage.borrow_mut().remove(&(course_id, student))) {
+        Some(_) => Ok("Enrollment request rejected".to_string()),
+        None => Err("Enrollment request not found".to_string()),
+    }
+}
+
+fn encode_invite_code(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| INVITE_CODE_ALPHABET[b as usize % INVITE_CODE_ALPHABET.len()] as char)
+        .collect()
+}
+
+#[ic_cdk::update]
+async fn create_invite_code(payload: CreateInviteCodePayload) -> Result<InviteCode, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(payload.course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    if payload.max_uses == Some(0) {
+        return Err("Max uses must be greater than zero".to_string());
+    }
+    if payload.expires_at.is_some_and(|expires_at| expires_at <= time()) {
+        return Err("Expiry must be in the future".to_string());
+    }
+    validate_cohort_choice(payload.course_id, payload.cohort_id)?;
+
+    let (seed,) = raw_rand()
+        .await
+        .map_err(|(code, msg)| format!("Failed to obtain randomness: {:?} {}", code, msg))?;
+
+    // Ownership may have changed while awaiting randomness; re-check.
+    ensure_permission(payload.course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    let code = seed
+        .chunks_exact(INVITE_CODE_LENGTH)
+        .map(encode_invite_code)
+        .find(|code| !INVITE_CODE_STORAGE.with(|storage| storage.borrow().contains_key(code)))
+        .ok_or_else(|| "Failed to generate a unique invite code, please retry".to_string())?;
+
+    let invite = InviteCode {
+        code: code.clone(),
+        course_id: payload.course_id,
+        cohort_id: payload.cohort_id,
+        created_by: caller,
+        max_uses: payload.max_uses,
+        uses: 0,
+        expires_at: payload.expires_at,
+        revoked: false,
+        created_at: time(),
+    };
+
+    INVITE_CODE_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(code, invite.clone());
+    });
+
+    Ok(invite)
+}
+
+#[ic_cdk::update]
+fn revoke_invite_code(code: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+
+    let mut invite = INVITE_CODE_STORAGE
+        .with(|storage| storage.borrow().get(&code))
+        .ok_or_else(|| "Invite code not found".to_string())?;
+    ensure_permission(invite.course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    invite.revoked = true;
+    INVITE_CODE_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(code, invite);
+    });
+
+    Ok("Invite code revoked".to_string())
+}
+
+#[ic_cdk::query]
+fn get_course_invite_codes(course_id: u64) -> Result<Vec<InviteCode>, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::ManageEnrollments)?;
+
+    Ok(INVITE_CODE_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, invite)| {
+                if invite.course_id == course_id {
+                    Some(invite)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }))
+}
+
+fn adjust_invite_uses(code: &str, delta: i32) {
+    INVITE_CODE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(mut invite) = storage.get(&code.to_string()) {
+            invite.uses = invite.uses.saturating_add_signed(delta);
+            storage.insert(code.to_string(), invite);
+        }
+    });
+}
+
+// Invite codes admit learners under any enrollment policy. Paid courses are
+// still charged; a use is held while the payment is in flight and released
+// if the learner ends up not enrolled.
+#[ic_cdk::update]
+async fn redeem_invite_code(code: String) -> Result<Enrollment, String> {
+    let caller = ic_cdk::caller();
+
+    let code = code.trim().to_uppercase();
+    let invite = INVITE_CODE_STORAGE
+        .with(|storage| storage.borrow().get(&code))
+        .ok_or_else(|| "Invalid invite code".to_string())?;
+
+    if invite.revoked {
+        return Err("Invite code has been revoked".to_string());
+    }
+    if invite.expires_at.is_some_and(|expires_at| time() > expires_at) {
+        return Err("Invite code has expired".to_string());
+    }
+    if invite.max_uses.is_some_and(|max_uses| invite.uses >= max_uses) {
+        return Err("Invite code has no uses left".to_string());
+    }
+
+    let course = get_course(invite.course_id).ok_or_else(|| "Course not found".to_string())?;
+    if course.price == 0 {
+        let enrollment = admit_student(caller, &course, invite.cohort_id)?;
+        adjust_invite_uses(&code, 1);
+        return Ok(enrollment);
+    }
+
+    check_admission(caller, &course, invite.cohort_id)?;
+    adjust_invite_uses(&code, 1);
+    let result = match charge_for_course(caller, &course).await {
+        Ok(purchase) => admit_paid_student(caller, &course, invite.cohort_id, purchase).await,
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        adjust_invite_uses(&code, -1);
+    }
+
+    result
+}
+
+// Enrolls a roster on the educator's behalf, bypassing the enrollment policy
+// and the course price. Since the seats are not paid for, only the course
+// owner can bulk enroll into a paid course.
+// Each principal is admitted independently and failures are reported per entry.
+#[ic_cdk::update]
+fn bulk_enroll(
+    course_id: u64,
+    students: Vec<Principal>,
+    cohort_id: Option<u64>,
+) -> Result<BulkEnrollmentResult, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_permission(course_id, caller, StaffPermission::ManageEnrollments)?;
+    if course.price > 0 && course.educator != caller {
+        return Err("Only the course owner can enroll students into a paid course for free".to_string());
+    }
+
+    if students.len() > MAX_BULK_ENROLLMENT {
+        return Err(format!("At most {} students can be enrolled at once", MAX_BULK_ENROLLMENT));
+    }
+    validate_cohort_choice(course_id, cohort_id)?;
+
+    let mut result = BulkEnrollmentResult {
+        enrolled: Vec::new(),
+        failed: Vec::new(),
+    };
+    for student in students {
+        match admit_student(student, &course, cohort_id) {
+            Ok(enrollment) => result.enrolled.push(enrollment),
+            Err(reason) => result.failed.push(BulkEnrollmentFailure { student, reason }),
+        }
+    }
+
+    Ok(result)
+}
+
+// Paid Enrollment
+fn canister_config() -> CanisterConfig {
+    CONFIG_STORAGE
+        .with(|storage| storage.borrow().get(&0))
+        .unwrap_or(CanisterConfig {
+            token_rewards_canister: None,
+            certificate_issuer_canister: None,
+            refund_window_secs: DEFAULT_REFUND_WINDOW_SECS,
+            updated_at: 0,
+        })
+}
+
+#[ic_cdk::update]
+fn set_canister_config(payload: CanisterConfigPayload) -> Result<CanisterConfig, String> {
+    let caller = ic_cdk::caller();
+
+    if !is_admin(caller) {
+        return Err("Only controllers can configure the canister".to_string());
+    }
+
+    let config = CanisterConfig {
+        token_rewards_canister: payload.token_rewards_canister,
+        certificate_issuer_canister: payload.certificate_issuer_canister,
+        refund_window_secs: payload.refund_window_secs,
+        updated_at: time(),
+    };
+
+    CONFIG_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(0, config.clone());
+    });
+
+    Ok(config)
+}
+
+#[ic_cdk::query]
+fn get_canister_config() -> CanisterConfig {
+    canister_config()
+}
+
+fn token_rewards_canister() -> Result<Principal, String> {
+    canister_config()
+        .token_rewards_canister
+        .ok_or_else(|| "Paid enrollment is not configured".to_string())
+}
+
+async fn charge_for_course(student: Principal, course: &Course) -> Result<Purchase, String> {
+    let payload = PurchasePayload {
+        buyer: student,
+        seller: course.educator,
+        course_id: course.id,
+        price: course.price,
+    };
+
+    let (result,): (Result<Purchase, String>,) =
+        ic_cdk::call(token_rewards_canister()?, "purchase_course", (payload,))
+            .await
+            .map_err(|(code, msg)| format!("Payment failed: {:?} {}", code, msg))?;
+
+    result.map_err(|e| format!("Payment failed: {}", e))
+}
+
+async fn refund_course_purchase(purchase_id: u64) -> Result<Purchase, String> {
+    let (result,): (Result<Purchase, String>,) =
+        ic_cdk::call(token_rewards_canister()?, "refund_purchase", (purchase_id,))
+            .await
+            .map_err(|(code, msg)| format!("Refund failed: {:?} {}", code, msg))?;
+
+    result.map_err(|e| format!("Refund failed: {}", e))
+}
+
+// Completes an enrollment that has already been paid for. State may have
+// changed while awaiting the payment, so admission is checked again and the
+// purchase is refunded if the learner can no longer be enrolled.
+async fn admit_paid_student(
+    student: Principal,
+    course: &Course,
+    cohort_id: Option<u64>,
+    purchase: Purchase,
+) -> Result<Enrollment, String> {
+    match admit_student(student, course, cohort_id) {
+        Ok(mut enrollment) => {
+            enrollment.purchase_id = Some(purchase.id);
+            ENROLLMENT_STORAGE.with(|storage| {
+                storage.borrow_mut().insert((student, course.id), enrollment.clone());
+            });
+            Ok(enrollment)
+        }
+        Err(e) => match refund_course_purchase(purchase.id).await {
+            Ok(_) => Err(format!("{}; the payment has been refunded", e)),
+            Err(refund_error) => Err(format!(
+                "{}; purchase {} could not be refunded: {}",
+                e, purchase.id, refund_error
+            )),
+        },
+    }
+}
+
+fn refund_window_open(enrollment: &Enrollment) -> bool {
+    let window_nanos = canister_config().refund_window_secs.saturating_mul(NANOS_PER_SECOND);
+    time() <= enrollment.enrolled_at.saturating_add(window_nanos)
+}
+
+// Kept for existing clients. Unlike `unenroll_from_course`, the learner stays
+// enrolled when the refund fails.
+#[ic_cdk::update]
+async fn request_course_refund(course_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(caller, course_id)))
+        .ok_or_else(|| "Not enrolled in this course".to_string())?;
+    let purchase_id = enrollment
+        .purchase_id
+        .ok_or_else(|| "This enrollment was not purchased".to_string())?;
+
+    if enrollment.completed {
+        return Err("Completed courses cannot be refunded".to_string());
+    }
+    if !refund_window_open(&enrollment) {
+        return Err("The refund window for this course has closed".to_string());
+    }
+
+    refund_course_purchase(purchase_id).await?;
+
+    // The refund has gone through, so the paid enrollment is dropped even if
+    // it changed while awaiting it; an error here would hide the refund.
+    let current = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(caller, course_id)))
+        .filter(|current| current.purchase_id == Some(purchase_id));
+    let current = match current {
+        Some(current) => current,
+        None => return Ok("Course purchase refunded; the enrollment had already ended".to_string()),
+    };
+
+    let payload = UnenrollPayload {
+        reason: None,
+        keep_progress: true,
+    };
+    match record_drop(caller, &current, payload, Some(purchase_id), None) {
+        Ok(_) => Ok("Course purchase refunded".to_string()),
+        Err(e) => Ok(format!("Course purchase refunded, but the enrollment could not be dropped: {}", e)),
+    }
+}
+
+// Course Staff
+fn staff_member(course_id: u64, member: Principal) -> Option<StaffMember> {
+    STAFF_STORAGE.with(|storage| storage.borrow().get(&(course_id, member)))
+}
+
+fn default_staff_permissions(role: &StaffRole) -> Vec<StaffPermission> {
+    match role {
+        StaffRole::Owner | StaffRole::CoInstructor => vec![
+            StaffPermission::EditContent,
+            StaffPermission::Grade,
+            StaffPermission::ManageEnrollments,
+            StaffPermission::ViewAnalytics,
+        ],
+        StaffRole::TeachingAssistant => vec![StaffPermission::Grade],
+    }
+}
+
+fn resolve_staff_permissions(payload: StaffPayload) -> Result<(StaffRole, Vec<StaffPermission>), String> {
+    if payload.role == StaffRole::Owner {
+        return Err("A course has exactly one owner; ownership cannot be granted".to_string());
+    }
+
+    let mut permissions = Vec::new();
+    for permission in payload
+        .permissions
+        .unwrap_or_else(|| default_staff_permissions(&payload.role))
+    {
+        if !permissions.contains(&permission) {
+            permissions.push(permission);
+        }
+    }
+
+    Ok((payload.role, permissions))
+}
+
+#[ic_cdk::update]
+fn invite_course_staff(
+    course_id: u64,
+    invitee: Principal,
+    payload: StaffPayload,
+) -> Result<StaffInvitation, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_course_owner(course_id, caller)?;
+
+    if invitee == Principal::anonymous() || invitee == course.educator {
+        return Err("Invalid staff invitee".to_string());
+    }
+    if staff_member(course_id, invitee).is_some() {
+        return Err("Already a member of the course staff".to_string());
+    }
+    let (role, permissions) = resolve_staff_permissions(payload)?;
+
+    let invitation = StaffInvitation {
+        course_id,
+        invitee,
+        role,
+        permissions,
+        invited_by: caller,
+        invited_at: time(),
+    };
+
+    STAFF_INVITATION_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((course_id, invitee), invitation.clone());
+    });
+
+    Ok(invitation)
+}
+
+#[ic_cdk::update]
+fn revoke_staff_invitation(course_id: u64, invitee: Principal) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    ensure_course_owner(course_id, caller)?;
+
+    match STAFF_INVITATION_STORAGE.with(|storage| storage.borrow_mut().remove(&(course_id, invitee))) {
+        Some(_) => Ok("Staff invitation revoked".to_string()),
+        None => Err("Staff invitation not found".to_string()),
+    }
+}
+
+#[ic_cdk::update]
+fn accept_staff_invitation(course_id: u64) -> Result<StaffMember, String> {
+    let caller = ic_cdk::caller();
+
+    let invitation = STAFF_INVITATION_STORAGE
+        .with(|storage| storage.borrow_mut().remove(&(course_id, caller)))
+        .ok_or_else(|| "Staff invitation not found".to_string())?;
+
+    let member = StaffMember {
+        course_id,
+        member: caller,
+        role: invitation.role,
+        permissions: invitation.permissions,
+        added_by: invitation.invited_by,
+        added_at: time(),
+    };
+
+    STAFF_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((course_id, caller), member.clone());
+    });
+
+    Ok(member)
+}
+
+#[ic_cdk::update]
+fn decline_staff_invitation(course_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+
+    match STAFF_INVITATION_STORAGE.with(|storage| storage.borrow_mut().remove(&(course_id, caller))) {
+        Some(_) => Ok("Staff invitation declined".to_string()),
+        None => Err("Staff invitation not found".to_string()),
+    }
+}
+
+#[ic_cdk::update]
+fn update_course_staff(
+    course_id: u64,
+    member: Principal,
+    payload: StaffPayload,
+) -> Result<StaffMember, String> {
+    let caller = ic_cdk::caller();
+    ensure_course_owner(course_id, caller)?;
+
+    let mut staff = staff_member(course_id, member)
+        .ok_or_else(|| "Staff member not found".to_string())?;
+    let (role, permissions) = resolve_staff_permissions(payload)?;
+    staff.role = role;
+    staff.permissions = permissions;
+
+    STAFF_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((course_id, member), staff.clone());
+    });
+
+    Ok(staff)
+}
+
+// The owner can remove anyone; staff members can remove themselves.
+#[ic_cdk::update]
+fn remove_course_staff(course_id: u64, member: Principal) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    if caller != member {
+        ensure_course_owner(course_id, caller)?;
+    }
+
+    match STAFF_STORAGE.with(|storage| storage.borrow_mut().remove(&(course_id, member))) {
+        Some(_) => Ok("Staff member removed".to_string()),
+        None => Err("Staff member not found".to_string()),
+    }
+}
+
+#[ic_cdk::query]
+fn get_course_staff(course_id: u64) -> Result<Vec<StaffMember>, String> {
+    let course = get_course(course_id).ok_or_else(|| "Course not found".to_string())?;
+
+    let mut staff = vec![StaffMember {
+        course_id,
+        member: course.educator,
+        role: StaffRole::Owner,
+        permissions: default_staff_permissions(&StaffRole::Owner),
+        added_by: course.educator,
+        added_at: course.created_at,
+    }];
+    STAFF_STORAGE.with(|storage| {
+        staff.extend(
+            storage
+                .borrow()
+                .range((course_id, Principal::management_canister())..)
+                .take_while(|((id, _), _)| *id == course_id)
+                .map(|(_, member)| member),
+        );
+    });
+
+    Ok(staff)
+}
+
+#[ic_cdk::query]
+fn get_pending_staff_invitations(course_id: u64) -> Result<Vec<StaffInvitation>, String> {
+    let caller = ic_cdk::caller();
+    ensure_course_owner(course_id, caller)?;
+
+    Ok(STAFF_INVITATION_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((course_id, Principal::management_canister())..)
+            .take_while(|((id, _), _)| *id == course_id)
+            .map(|(_, invitation)| invitation)
+            .collect()
+    }))
+}
+
+#[ic_cdk::query]
+fn get_my_staff_invitations() -> Vec<StaffInvitation> {
+    let caller = ic_cdk::caller();
+
+    STAFF_INVITATION_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, invitation)| {
+                if invitation.invitee == caller {
+                    Some(invitation)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_my_staff_courses() -> Vec<Course> {
+    let caller = ic_cdk::caller();
+
+    let course_ids: Vec<u64> = STAFF_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter(|((_, member), _)| *member == caller)
+            .map(|((course_id, _), _)| course_id)
+            .collect()
+    });
+
+    course_ids.into_iter().filter_map(get_course).collect()
+}
+
+// Unenrollment
+fn student_course_progress(student: Principal, course_id: u64) -> Vec<LessonProgress> {
+    PROGRESS_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((student, course_id, 0)..=(student, course_id, u64::MAX))
+            .map(|(_, progress)| progress)
+            .collect()
+    })
+}
+
+fn last_completed_lesson(progress: &[LessonProgress]) -> Option<u64> {
+    progress
+        .iter()
+        .filter(|p| p.completed)
+        .max_by_key(|p| p.completed_at)
+        .map(|p| p.lesson_id)
+}
+
+// Leaves a course, freeing any cohort seat. Purchased enrollments are
+// refunded while the refund window is open; a failed refund is recorded on
+// the drop and does not keep the learner enrolled. Lesson progress is kept
+// for a later re-enrollment only when asked to.
+#[ic_cdk::update]
+async fn unenroll_from_course(course_id: u64, payload: UnenrollPayload) -> Result<DropRecord, String> {
+    let caller = ic_cdk::caller();
+
+    if payload.reason.as_ref().is_some_and(|reason| reason.chars().count() > 1000) {
+        return Err("Drop reason is too long".to_string());
+    }
+
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(caller, course_id)))
+        .ok_or_else(|| "Not enrolled in this course".to_string())?;
+
+    if enrollment.completed {
+        return Err("Completed courses cannot be dropped".to_string());
+    }
+
+    let mut refunded_purchase = None;
+    let mut refund_error = None;
+    if let Some(purchase_id) = enrollment.purchase_id {
+        if refund_window_open(&enrollment) {
+            match refund_course_purchase(purchase_id).await {
+                Ok(_) => refunded_purchase = Some(purchase_id),
+                Err(e) => refund_error = Some(e),
+            }
+        }
+    }
+
+    record_drop(caller, &enrollment, payload, refunded_purchase, refund_error)
+}
+
+fn record_drop(
+    student: Principal,
+    enrollment: &Enrollment,
+    payload: UnenrollPayload,
+    refunded_purchase: Option<u64>,
+    refund_error: Option<String>,
+) -> Result<DropRecord, String> {
+    let course_id = enrollment.course_id;
+
+    // The enrollment may have changed while awaiting the refund.
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(student, course_id)))
+        .filter(|current| current.enrolled_at == enrollment.enrolled_at)
+        .ok_or_else(|| "Not enrolled in this course".to_string())?;
+
+    let progress = student_course_progress(student, course_id);
+    let drop = DropRecord {
+        id: get_next_id(),
+        student,
+        course_id,
+        cohort_id: enrollment.cohort_id,
+        reason: payload.reason.filter(|reason| !reason.trim().is_empty()),
+        lessons_completed: progress.iter().filter(|p| p.completed).count() as u32,
+        last_completed_lesson: last_completed_lesson(&progress),
+        kept_progress: payload.keep_progress,
+        refunded_purchase,
+        refund_error,
+        enrolled_at: enrollment.enrolled_at,
+        dropped_at: time(),
+    };
+
+    remove_enrollment(student, course_id);
+    if !payload.keep_progress {
+        PROGRESS_STORAGE.with(|storage| {
+            let mut storage = storage.borrow_mut();
+            for p in &progress {
+                storage.remove(&(student, course_id, p.lesson_id));
+            }
+        });
+    }
+
+    DROP_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(drop.id, drop.clone());
+    });
+
+    Ok(drop)
+}
+
+#[ic_cdk::query]
+fn get_course_drops(course_id: u64) -> Result<Vec<DropRecord>, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::ViewAnalytics)?;
+
+    Ok(DROP_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, drop)| {
+                if drop.course_id == course_id {
+                    Some(drop)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }))
+}
+
+// Analytics
+fn median(values: &mut [u64]) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len() % 2 == 1 {
+        Some(values[mid])
+    } else {
+        Some((values[mid - 1] + values[mid]) / 2)
+    }
+}
+
+fn percent_of(count: u32, total: u32) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+// `days` limits the enrollment time series to the most recent days, today
+// included.
+#[ic_cdk::query]
+fn get_course_analytics(course_id: u64, days: u32) -> Result<CourseAnalytics, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_permission(course_id, caller, StaffPermission::ViewAnalytics)?;
+
+    if days == 0 || days > MAX_ANALYTICS_DAYS {
+        return Err(format!("Days must be between 1 and {}", MAX_ANALYTICS_DAYS));
+    }
+
+    let enrollments = get_course_enrollments(course_id);
+    let drops = get_course_drops(course_id)?;
+
+    let mut lessons = course.lessons.clone();
+    lessons.sort_by_key(|lesson| lesson.order);
+    let mut lesson_counts = vec![0u32; lessons.len()];
+
+    let mut completion_secs = Vec::new();
+    let mut drop_off: Vec<DropOffPoint> = Vec::new();
+    let mut record_drop_off = |lesson: Option<u64>, dropped: bool| {
+        let index = match drop_off.iter().position(|p| p.last_completed_lesson == lesson) {
+            Some(index) => index,
+            None => {
+                drop_off.push(DropOffPoint {
+                    last_completed_lesson: lesson,
+                    dropped: 0,
+                    stalled: 0,
+                });
+                drop_off.len() - 1
+            }
+        };
+        if dropped {
+            drop_off[index].dropped += 1;
+        } else {
+            drop_off[index].stalled += 1;
+        }
+    };
+
+    for enrollment in &enrollments {
+        let progress = student_course_progress(enrollment.student, course_id);
+        for p in progress.iter().filter(|p| p.completed) {
+            if let Some(index) = lessons.iter().position(|lesson| lesson.id == p.lesson_id) {
+                lesson_counts[index] += 1;
+            }
+        }
+
+        if enrollment.completed {
+            if let Some(completion_date) = enrollment.completion_date {
+                completion_secs
+                    .push(completion_date.saturating_sub(enrollment.enrolled_at) / NANOS_PER_SECOND);
+            }
+        } else {
+            record_drop_off(last_completed_lesson(&progress), false);
+        }
+    }
+    for drop in &drops {
+        record_drop_off(drop.last_completed_lesson, true);
+    }
+    drop_off.sort_by_key(|p| std::cmp::Reverse(p.dropped + p.stalled));
+
+    let active_enrollments = enrollments.len() as u32;
+    let completed_enrollments = enrollments.iter().filter(|e| e.completed).count() as u32;
+    let dropped_enrollments = drops.len() as u32;
+
+    let lesson_funnel = lessons
+        .iter()
+        .zip(lesson_counts)
+        .map(|(lesson, completed_count)| LessonFunnelStep {
+            lesson_id: lesson.id,
+            title: lesson.title.clone(),
+            order: lesson.order,
+            completed_count,
+            percent_of_enrolled: percent_of(completed_count, active_enrollments),
+        })
+        .collect();
+
+    let today = time() / NANOS_PER_DAY;
+    let first_day = today.saturating_sub(days as u64 - 1);
+    let mut daily_counts = vec![0u32; (today - first_day + 1) as usize];
+    let enrolled_days = enrollments
+        .iter()
+        .map(|e| e.enrolled_at)
+        .chain(drops.iter().map(|d| d.enrolled_at))
+        .map(|enrolled_at| enrolled_at / NANOS_PER_DAY);
+    for day in enrolled_days {
+        if day >= first_day && day <= today {
+            daily_counts[(day - first_day) as usize] += 1;
+        }
+    }
+    let daily_enrollments = daily_counts
+        .into_iter()
+        .enumerate()
+        .map(|(offset, enrollments)| DailyEnrollmentCount {
+            day_start: (first_day + offset as u64) * NANOS_PER_DAY,
+            enrollments,
+        })
+        .collect();
+
+    Ok(CourseAnalytics {
+        course_id,
+        active_enrollments,
+        completed_enrollments,
+        dropped_enrollments,
+        completion_rate: percent_of(completed_enrollments, active_enrollments + dropped_enrollments),
+        median_completion_secs: median(&mut completion_secs),
+        lesson_funnel,
+        drop_off,
+        daily_enrollments,
+        generated_at: time(),
+    })
+}
+
+// Learner Progress
+fn build_course_progress(enrollment: &Enrollment) -> Option<CourseProgressDetail> {
+    let course = get_course(enrollment.course_id)?;
+    let progress = student_course_progress(enrollment.student, course.id);
+
+    let mut lessons = course.lessons.clone();
+    lessons.sort_by_key(|lesson| lesson.order);
+
+    let details: Vec<LessonProgressDetail> = lessons
+        .iter()
+        .map(|lesson| {
+            let lesson_progress = progress.iter().find(|p| p.lesson_id == lesson.id && p.completed);
+            LessonProgressDetail {
+                lesson_id: lesson.id,
+                title: lesson.title.clone(),
+                order: lesson.order,
+                duration_secs: lesson.duration_secs,
+                completed: lesson_progress.is_some(),
+                completed_at: lesson_progress.and_then(|p| p.completed_at),
+                due_at: lesson_due_at(lesson, enrollment),
+                late: lesson_progress.is_some_and(|p| p.late),
+            }
+        })
+        .collect();
+
+    let remaining: Vec<&LessonProgressDetail> = details.iter().filter(|d| !d.completed).collect();
+    let next_lesson = adaptive_next_lesson(enrollment, &course).map(|recommendation| NextLesson {
+        lesson_id: recommendation.lesson_id,
+        title: recommendation.title,
+        order: recommendation.order,
+    });
+    let total_lessons = details.len() as u32;
+    let completed_lessons = total_lessons - remaining.len() as u32;
+
+    let summary = CourseProgressSummary {
+        course_id: course.id,
+        course_title: course.title,
+        enrolled_at: enrollment.enrolled_at,
+        completed: enrollment.completed,
+        total_lessons,
+        completed_lessons,
+        percent_complete: percent_of(completed_lessons, total_lessons),
+        next_lesson,
+        last_activity_at: details
+            .iter()
+            .filter_map(|d| d.completed_at)
+            .chain(enrollment.completion_date)
+            .fold(enrollment.enrolled_at, u64::max),
+        estimated_remaining_secs: remaining.iter().map(|d| d.duration_secs).sum(),
+    };
+
+    Some(CourseProgressDetail {
+        summary,
+        lessons: details,
+    })
+}
+
+#[ic_cdk::query]
+fn get_my_progress() -> Vec<CourseProgressSummary> {
+    let caller = ic_cdk::caller();
+
+    let mut summaries: Vec<CourseProgressSummary> = get_student_enrollments(caller)
+        .iter()
+        .filter_map(build_course_progress)
+        .map(|detail| detail.summary)
+        .collect();
+    summaries.sort_by_key(|summary| std::cmp::Reverse(summary.last_activity_at));
+
+    summaries
+}
+
+#[ic_cdk::query]
+fn get_my_course_progress(course_id: u64) -> Result<CourseProgressDetail, String> {
+    let caller = ic_cdk::caller();
+
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(caller, course_id)))
+        .ok_or_else(|| "Not enrolled in this course".to_string())?;
+
+    build_course_progress(&enrollment).ok_or_else(|| "Course not found".to_string())
+}
+
+// Lesson Content
+// Drops control characters other than newlines and tabs, and trims.
+fn sanitize_text(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn validate_title(title: &str, what: &str) -> Result<String, String> {
+    let title = sanitize_text(title).replace(['\n', '\t'], " ");
+    if title.is_empty() {
+        return Err(format!("{} cannot be empty", what));
+    }
+    if title.chars().count() > MAX_TITLE_CHARS {
+        return Err(format!("{} is too long", what));
+    }
+    Ok(title)
+}
+
+// Removes raw HTML tags outside code spans and fenced code blocks. A `<`
+// only starts a tag when followed by a letter, `/`, `!` or `?`, so
+// comparisons and blockquotes are kept; `<https://...>` autolinks are kept
+// too. A tag left open at the end of a line loses its `<`.
+fn strip_html_tags(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut in_fence = false;
+
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            stripped.push('\n');
+        }
+        let fence = line.trim_start().starts_with("```");
+        if fence {
+            in_fence = !in_fence;
+        }
+        if in_fence || fence {
+            stripped.push_str(line);
+            continue;
+        }
+
+        let mut in_code = false;
+        let mut rest = line;
+        while let Some(c) = rest.chars().next() {
+            let after = &rest[c.len_utf8()..];
+            if c == '`' {
+                in_code = !in_code;
+            }
+            let opens_tag = c == '<'
+                && !in_code
+                && after
+                    .chars()
+                    .next()
+                    .is_some_and(|next| next.is_ascii_alphabetic() || "/!?".contains(next));
+            if !opens_tag {
+                stripped.push(c);
+                rest = after;
+                continue;
+            }
+            match after.find('>') {
+                Some(end) if after.starts_with("https://") => {
+                    stripped.push_str(&rest[..end + 2]);
+                    rest = &after[end + 1..];
+                }
+                Some(end) => rest = &after[end + 1..],
+                None => rest = after,
+            }
+        }
+    }
+
+    stripped
+}
+
+// Link targets of inline links and reference definitions must not use a
+// scheme that runs script or embeds content.
+fn check_markdown_links(text: &str) -> Result<(), String> {
+    let inline_targets = text.match_indices("](").map(|(index, _)| &text[index + 2..]);
+    let reference_targets = text.lines().filter_map(|line| {
+        let line = line.trim_start();
+        if !line.starts_with('[') {
+            return None;
+        }
+        line.find("]:").map(|index| &line[index + 2..])
+    });
+
+    for target in inline_targets.chain(reference_targets) {
+        let target: String = target
+            .chars()
+            .filter(|c| !c.is_whitespace() && !c.is_control())
+            .take(16)
+            .collect::<String>()
+            .trim_start_matches('<')
+            .to_lowercase();
+        if BLOCKED_LINK_SCHEMES.iter().any(|scheme| target.starts_with(scheme)) {
+            return Err("Markdown links cannot use script or data URLs".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn validate_url(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    if !url.starts_with("https://") || url.len() <= "https://".len() {
+        return Err("Links must be https URLs".to_string());
+    }
+    if url.len() > MAX_URL_CHARS {
+        return Err("URL is too long".to_string());
+    }
+    if url.chars().any(|c| c.is_whitespace() || c.is_control() || c == '"' || c == '<' || c == '>') {
+        return Err("URL contains invalid characters".to_string());
+    }
+    Ok(url.to_string())
+}
+
+// Validates and sanitizes one block and returns it with its estimated
+// duration in seconds.
+fn prepare_content_block(course_id: u64, block: LessonContent) -> Result<(LessonContent, u64), String> {
+    match block {
+        LessonContent::Markdown { text } => {
+            if text.chars().count() > MAX_MARKDOWN_CHARS {
+                return Err("Markdown text is too long".to_string());
+            }
+            // Raw HTML is stripped and script links are rejected; other
+            // escaping is left to the renderer so quotes and code keep their
+            // `>` and `<`.
+            let text = strip_html_tags(&sanitize_text(&text));
+            if text.trim().is_empty() {
+                return Err("Markdown text cannot be empty".to_string());
+            }
+            check_markdown_links(&text)?;
+            let words = text.split_whitespace().count() as u64;
+            let secs = (words * 60).div_ceil(READING_WORDS_PER_MINUTE);
+            Ok((LessonContent::Markdown { text }, secs))
+        }
+        LessonContent::Video { url, duration_secs } => {
+            if duration_secs == 0 || duration_secs > MAX_VIDEO_SECS {
+                return Err(format!(
+                    "Video duration must be between 1 and {} seconds",
+                    MAX_VIDEO_SECS
+                ));
+            }
+            let url = validate_url(&url)?;
+            Ok((LessonContent::Video { url, duration_secs }, duration_secs))
+        }
+        LessonContent::CodeSnippet { language, code } => {
+            let language = language.trim().to_lowercase();
+            if language.is_empty()
+                || language.len() > 30
+                || !language.chars().all(|c| c.is_ascii_alphanumeric() || "+#.-_".contains(c))
+            {
+                return Err("Code snippets need a valid language name".to_string());
+            }
+            if code.chars().count() > MAX_CODE_CHARS {
+                return Err("Code snippet is too long".to_string());
+            }
+            let code: String = code
+                .chars()
+                .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+                .collect();
+            if code.trim().is_empty() {
+                return Err("Code snippet cannot be empty".to_string());
+            }
+            let secs = code.lines().count() as u64 * CODE_SECS_PER_LINE;
+            Ok((LessonContent::CodeSnippet { language, code }, secs))
+        }
+        LessonContent::Attachment { file_name, url, size_bytes } => {
+            let file_name = validate_title(&file_name, "Attachment file name")?;
+            if file_name.contains(['/', '\\']) {
+                return Err("Attachment file name cannot contain path separators".to_string());
+            }
+            let url = validate_url(&url)?;
+            Ok((LessonContent::Attachment { file_name, url, size_bytes }, 0))
+        }
+        LessonContent::ExternalLink { url, title } => {
+            let title = validate_title(&title, "Link title")?;
+            let url = validate_url(&url)?;
+            Ok((LessonContent::ExternalLink { url, title }, 0))
+        }
+        LessonContent::Quiz { exam_id } => {
+            let exam = EXAM_STORAGE
+                .with(|storage| storage.borrow().get(&exam_id))
+                .filter(|exam| exam.course_id == course_id)
+                .ok_or_else(|| format!("Quiz refers to unknown exam {}", exam_id))?;
+            Ok((LessonContent::Quiz { exam_id }, exam.time_limit_secs))
+        }
+    }
+}
+
+fn prepare_lesson_content(
+    course_id: u64,
+    content: Vec<LessonContent>,
+) -> Result<(Vec<LessonContent>, u64), String> {
+    if content.is_empty() {
+        return Err("A lesson needs at least one content block".to_string());
+    }
+    if content.len() > MAX_LESSON_BLOCKS {
+        return Err(format!("A lesson can have at most {} content blocks", MAX_LESSON_BLOCKS));
+    }
+
+    let mut blocks = Vec::with_capacity(content.len());
+    let mut duration_secs = 0;
+    for block in content {
+        let (block, secs) = prepare_content_block(course_id, block)?;
+        blocks.push(block);
+        duration_secs += secs;
+    }
+    Ok((blocks, duration_secs))
+}
+
+fn build_lesson(course_id: u64, lesson_id: u64, payload: CreateLessonPayload) -> Result<Lesson, String> {
+    let title = validate_title(&payload.title, "Lesson title")?;
+    let (content, duration_secs) = prepare_lesson_content(course_id, payload.content)?;
+    if let Some(deadline) = &payload.deadline {
+        validate_deadline(deadline)?;
+    }
+    if let Some(unlock) = &payload.unlock {
+        validate_unlock(unlock)?;
+    }
+
+    let lesson = Lesson {
+        id: lesson_id,
+        title,
+        content,
+        duration_secs,
+        order: payload.order,
+        deadline: payload.deadline,
+        unlock: payload.unlock,
+        branches: Vec::new(),
+    };
+    validate_lesson_schedule(&lesson)?;
+
+    Ok(lesson)
+}
+
+// Lesson titles are search terms, so the catalog entry is refreshed too.
+fn store_course_lessons(mut course: Course) -> Course {
+    course.duration_secs = course.lessons.iter().map(|lesson| lesson.duration_secs).sum();
+    course.updated_at = time();
+
+    let previous = COURSE_STORAGE.with(|storage| storage.borrow_mut().insert(course.id, course.clone()));
+    if let Some(previous) = previous {
+        remove_from_catalog(&previous);
+    }
+    add_to_catalog(&course);
+
+    course
+}
+
+#[ic_cdk::update]
+fn add_lesson(course_id: u64, payload: CreateLessonPayload) -> Result<Course, String> {
+    let caller = ic_cdk::caller();
+    let mut course = ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    let lesson_id = course.lessons.iter().map(|lesson| lesson.id).max().unwrap_or(0) + 1;
+    let lesson = build_lesson(course_id, lesson_id, payload)?;
+    course.lessons.push(lesson);
+
+    Ok(store_course_lessons(course))
+}
+
+#[ic_cdk::update]
+fn update_lesson(course_id: u64, lesson_id: u64, payload: UpdateLessonPayload) -> Result<Course, String> {
+    let caller = ic_cdk::caller();
+    let mut course = ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    let lesson = course
+        .lessons
+        .iter_mut()
+        .find(|lesson| lesson.id == lesson_id)
+        .ok_or_else(|| "Lesson not found".to_string())?;
+
+    if let Some(title) = payload.title {
+        lesson.title = validate_title(&title, "Lesson title")?;
+    }
+    if let Some(content) = payload.content {
+        let (content, duration_secs) = prepare_lesson_content(course_id, content)?;
+        lesson.content = content;
+        lesson.duration_secs = duration_secs;
+    }
+    if let Some(order) = payload.order {
+        lesson.order = order;
+    }
+
+    Ok(store_course_lessons(course))
+}
+
+// Assets
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Blob chunks are keyed by the raw digest, since stable map tuple keys must
+// have a bounded size.
+fn digest_from_hex(sha256: &str) -> [u8; 32] {
+    let mut digest = [0u8; 32];
+    for (byte, pair) in digest.iter_mut().zip(sha256.as_bytes().chunks(2)) {
+        *byte = std::str::from_utf8(pair)
+            .ok()
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            .unwrap_or(0);
+    }
+    digest
+}
+
+fn asset_type_limit(content_type: &str) -> Option<u64> {
+    ASSET_TYPES
+        .iter()
+        .find(|(accepted, _)| *accepted == content_type)
+        .map(|(_, limit)| *limit)
+}
+
+fn validate_asset_file_name(file_name: &str) -> Result<String, String> {
+    let file_name = validate_title(file_name, "File name")?;
+    if file_name.contains(['/', '\\', '?', '#']) {
+        return Err("File name contains invalid characters".to_string());
+    }
+    Ok(file_name)
+}
+
+fn asset_quota(owner: Principal) -> u64 {
+    ASSET_QUOTA_STORAGE
+        .with(|storage| storage.borrow().get(&owner))
+        .unwrap_or(DEFAULT_ASSET_QUOTA_BYTES)
+}
+
+fn owner_assets(owner: Principal) -> Vec<Asset> {
+    ASSET_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, asset)| if asset.owner == owner { Some(asset) } else { None })
+            .collect()
+    })
+}
+
+// Bytes stored for an owner count once per distinct content hash.
+fn compute_asset_usage(owner: Principal) -> AssetUsage {
+    let mut hashes: Vec<(String, u64)> = owner_assets(owner)
+        .into_iter()
+        .map(|asset| (asset.sha256, asset.size_bytes))
+        .collect();
+    hashes.sort();
+    hashes.dedup();
+
+    let reserved_bytes = UPLOAD_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter(|(_, upload)| upload.owner == owner)
+            .map(|(_, upload)| upload.size_bytes)
+            .sum()
+    });
+
+    AssetUsage {
+        used_bytes: hashes.iter().map(|(_, size)| size).sum(),
+        reserved_bytes,
+        quota_bytes: asset_quota(owner),
+    }
+}
+
+fn discard_upload(upload_id: u64) {
+    UPLOAD_STORAGE.with(|storage| {
+        storage.borrow_mut().remove(&upload_id);
+    });
+    UPLOAD_CHUNK_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        let keys: Vec<(u64, u32)> = storage
+            .range((upload_id, 0)..=(upload_id, u32::MAX))
+            .map(|(key, _)| key)
+            .collect();
+        for key in keys {
+            storage.remove(&key);
+        }
+    });
+}
+
+fn discard_expired_uploads(owner: Principal) {
+    let cutoff = time().saturating_sub(UPLOAD_EXPIRY_SECS * NANOS_PER_SECOND);
+    let expired: Vec<u64> = UPLOAD_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter(|(_, upload)| upload.owner == owner && upload.started_at < cutoff)
+            .map(|(id, _)| id)
+            .collect()
+    });
+    for upload_id in expired {
+        discard_upload(upload_id);
+    }
+}
+
+fn caller_upload(upload_id: u64, caller: Principal) -> Result<UploadSession, String> {
+    UPLOAD_STORAGE
+        .with(|storage| storage.borrow().get(&upload_id))
+        .filter(|upload| upload.owner == caller)
+        .ok_or_else(|| "Upload not found".to_string())
+}
+
+#[ic_cdk::update]
+fn begin_asset_upload(payload: BeginAssetUploadPayload) -> Result<UploadSession, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(payload.course_id, caller, StaffPermission::EditContent)?;
+
+    let file_name = validate_asset_file_name(&payload.file_name)?;
+    let content_type = payload.content_type.trim().to_lowercase();
+    let limit = asset_type_limit(&content_type)
+        .ok_or_else(|| format!("Unsupported content type '{}'", content_type))?;
+    if payload.size_bytes == 0 || payload.size_bytes > limit {
+        return Err(format!("{} files must be between 1 and {} bytes", content_type, limit));
+    }
+
+    discard_expired_uploads(caller);
+    let usage = compute_asset_usage(caller);
+    if usage.used_bytes + usage.reserved_bytes + payload.size_bytes > usage.quota_bytes {
+        return Err("Asset storage quota exceeded".to_string());
+    }
+
+    let upload = UploadSession {
+        id: get_next_id(),
+        course_id: payload.course_id,
+        owner: caller,
+        file_name,
+        content_type,
+        size_bytes: payload.size_bytes,
+        access: payload.access,
+        received_bytes: 0,
+        started_at: time(),
+    };
+
+    UPLOAD_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(upload.id, upload.clone());
+    });
+
+    Ok(upload)
+}
+
+// Chunks may arrive in any order; re-sending an index replaces that chunk.
+#[ic_cdk::update]
+fn upload_asset_chunk(upload_id: u64, chunk_index: u32, data: ByteBuf) -> Result<UploadSession, String> {
+    let caller = ic_cdk::caller();
+    let mut upload = caller_upload(upload_id, caller)?;
+
+    if data.is_empty() || data.len() > MAX_UPLOAD_CHUNK_BYTES {
+        return Err(format!("Chunks must be between 1 and {} bytes", MAX_UPLOAD_CHUNK_BYTES));
+    }
+
+    let replaced = UPLOAD_CHUNK_STORAGE
+        .with(|storage| storage.borrow().get(&(upload_id, chunk_index)))
+        .map(|chunk| chunk.len() as u64)
+        .unwrap_or(0);
+    let received_bytes = upload.received_bytes - replaced + data.len() as u64;
+    if received_bytes > upload.size_bytes {
+        return Err("Upload exceeds its declared size".to_string());
+    }
+
+    UPLOAD_CHUNK_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((upload_id, chunk_index), data.into_vec());
+    });
+    upload.received_bytes = received_bytes;
+    UPLOAD_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(upload_id, upload.clone());
+    });
+
+    Ok(upload)
+}
+
+// Reads an upload's chunks in order, one at a time, so finalizing never holds
+// more than one chunk in memory.
+fn for_each_upload_chunk(upload_id: u64, mut apply: impl FnMut(&[u8])) {
+    UPLOAD_CHUNK_STORAGE.with(|storage| {
+        for (_, chunk) in storage.borrow().range((upload_id, 0)..=(upload_id, u32::MAX)) {
+            apply(&chunk);
+        }
+    });
+}
+
+// Verifies the upload is complete, hashes it and stores it as a blob in
+// fixed-size chunks unless identical content is already stored.
+#[ic_cdk::update]
+fn finalize_asset_upload(upload_id: u64) -> Result<Asset, String> {
+    let caller = ic_cdk::caller();
+    let upload = caller_upload(upload_id, caller)?;
+    ensure_permission(upload.course_id, caller, StaffPermission::EditContent)?;
+
+    let contiguous = UPLOAD_CHUNK_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((upload_id, 0)..=(upload_id, u32::MAX))
+            .enumerate()
+            .all(|(expected, ((_, index), _))| index as usize == expected)
+    });
+    if !contiguous || upload.received_bytes != upload.size_bytes {
+        return Err("Upload is incomplete; chunks must be numbered from 0 without gaps".to_string());
+    }
+
+    let mut hasher = Sha256::new();
+    for_each_upload_chunk(upload_id, |chunk| hasher.update(chunk));
+    let digest: [u8; 32] = hasher.finalize().into();
+    let sha256 = to_hex(&digest);
+
+    match BLOB_STORAGE.with(|storage| storage.borrow().get(&sha256)) {
+        Some(mut blob) => {
+            blob.ref_count += 1;
+            BLOB_STORAGE.with(|storage| {
+                storage.borrow_mut().insert(sha256.clone(), blob);
+            });
+        }
+        None => {
+            let mut chunk_count = 0u32;
+            let mut buffer = Vec::with_capacity(ASSET_CHUNK_BYTES as usize);
+            for_each_upload_chunk(upload_id, |chunk| {
+                for piece in chunk.chunks(ASSET_CHUNK_BYTES as usize) {
+                    let room = ASSET_CHUNK_BYTES as usize - buffer.len();
+                    let (head, tail) = piece.split_at(room.min(piece.len()));
+                    buffer.extend_from_slice(head);
+                    if buffer.len() == ASSET_CHUNK_BYTES as usize {
+                        let full = std::mem::take(&mut buffer);
+                        BLOB_CHUNK_STORAGE.with(|storage| {
+                            storage.borrow_mut().insert((digest, chunk_count), full);
+                        });
+                        chunk_count += 1;
+                    }
+                    buffer.extend_from_slice(tail);
+                }
+            });
+            if !buffer.is_empty() {
+                BLOB_CHUNK_STORAGE.with(|storage| {
+                    storage.borrow_mut().insert((digest, chunk_count), buffer);
+                });
+                chunk_count += 1;
+            }
+            BLOB_STORAGE.with(|storage| {
+                storage.borrow_mut().insert(
+                    sha256.clone(),
+                    StoredBlob {
+                        size_bytes: upload.size_bytes,
+                        chunk_count,
+                        ref_count: 1,
+                    },
+                );
+            });
+        }
+    }
+    discard_upload(upload_id);
+
+    let asset = Asset {
+        id: get_next_id(),
+        course_id: upload.course_id,
+        owner: caller,
+        file_name: upload.file_name,
+        content_type: upload.content_type,
+        size_bytes: upload.size_bytes,
+        sha256,
+        access: upload.access,
+        created_at: time(),
+    };
+
+    ASSET_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(asset.id, asset.clone());
+    });
+
+    Ok(asset)
+}
+
+#[ic_cdk::update]
+fn cancel_asset_upload(upload_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    caller_upload(upload_id, caller)?;
+
+    discard_upload(upload_id);
+
+    Ok("Upload cancelled".to_string())
+}
+
+// The uploader or course staff with content rights can delete an asset; the
+// blob is freed once no asset refers to it.
+#[ic_cdk::update]
+fn delete_asset(asset_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+
+    let asset = get_asset(asset_id).ok_or_else(|| "Asset not found".to_string())?;
+    if asset.owner != caller {
+        ensure_permission(asset.course_id, caller, StaffPermission::EditContent)?;
+    }
+
+    ASSET_STORAGE.with(|storage| {
+        storage.borrow_mut().remove(&asset_id);
+    });
+
+    if let Some(mut blob) = BLOB_STORAGE.with(|storage| storage.borrow().get(&asset.sha256)) {
+        blob.ref_count = blob.ref_count.saturating_sub(1);
+        if blob.ref_count == 0 {
+            BLOB_STORAGE.with(|storage| {
+                storage.borrow_mut().remove(&asset.sha256);
+            });
+            let digest = digest_from_hex(&asset.sha256);
+            BLOB_CHUNK_STORAGE.with(|storage| {
+                let mut storage = storage.borrow_mut();
+                for index in 0..blob.chunk_count {
+                    storage.remove(&(digest, index));
+                }
+            });
+        } else {
+            BLOB_STORAGE.with(|storage| {
+                storage.borrow_mut().insert(asset.sha256.clone(), blob);
+            });
+        }
+    }
+
+    Ok("Asset deleted successfully".to_string())
+}
+
+#[ic_cdk::query]
+fn get_asset(asset_id: u64) -> Option<Asset> {
+    ASSET_STORAGE.with(|storage| storage.borrow().get(&asset_id))
+}
+
+#[ic_cdk::query]
+fn get_course_assets(course_id: u64) -> Vec<Asset> {
+    ASSET_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, asset)| {
+                if asset.course_id == course_id {
+                    Some(asset)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_my_asset_usage() -> AssetUsage {
+    compute_asset_usage(ic_cdk::caller())
+}
+
+#[ic_cdk::update]
+fn set_asset_quota(educator: Principal, quota_bytes: u64) -> Result<AssetUsage, String> {
+    let caller = ic_cdk::caller();
+
+    if !is_admin(caller) {
+        return Err("Only controllers can change asset quotas".to_string());
+    }
+
+    ASSET_QUOTA_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(educator, quota_bytes);
+    });
+
+    Ok(compute_asset_usage(educator))
+}
+
+fn can_access_asset(asset: &Asset, principal: Principal) -> bool {
+    match asset.access {
+        AssetAccess::Public => true,
+        AssetAccess::EnrolledOnly => {
+            let course = match get_course(asset.course_id) {
+                Some(course) => course,
+                None => return false,
+            };
+            course.educator == principal
+                || staff_member(course.id, principal).is_some()
+                || ENROLLMENT_STORAGE.with(|storage| {
+                    storage.borrow().contains_key(&(principal, course.id))
+                })
+        }
+    }
+}
+
+#[ic_cdk::update]
+async fn create_asset_access_token(asset_id: u64) -> Result<AssetAccessToken, String> {
+    let caller = ic_cdk::caller();
+
+    let asset = get_asset(asset_id).ok_or_else(|| "Asset not found".to_string())?;
+    if caller == Principal::anonymous() || !can_access_asset(&asset, caller) {
+        return Err("Not allowed to access this asset".to_string());
+    }
+
+    let (seed,) = raw_rand()
+        .await
+        .map_err(|(code, msg)| format!("Failed to obtain randomness: {:?} {}", code, msg))?;
+
+    let now = time();
+    ASSET_TOKEN_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        let expired: Vec<String> = storage
+            .iter()
+            .filter(|(_, token)| token.expires_at < now)
+            .map(|(key, _)| key)
+            .collect();
+        for key in expired {
+            storage.remove(&key);
+        }
+    });
+
+    let token = AssetAccessToken {
+        token: to_hex(&seed[..16]),
+        asset_id,
+        holder: caller,
+        expires_at: now + ASSET_TOKEN_TTL_SECS * NANOS_PER_SECOND,
+    };
+
+    ASSET_TOKEN_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(token.token.clone(), token.clone());
+    });
+
+    Ok(token)
+}
+
+// Tokens are re-checked against the holder's current enrollment.
+fn check_asset_token(asset: &Asset, token: Option<&str>) -> bool {
+    if asset.access == AssetAccess::Public {
+        return true;
+    }
+    token
+        .and_then(|token| ASSET_TOKEN_STORAGE.with(|storage| storage.borrow().get(&token.to_string())))
+        .is_some_and(|token| {
+            token.asset_id == asset.id && token.expires_at >= time() && can_access_asset(asset, token.holder)
+        })
+}
+
+fn read_blob_range(sha256: &str, start: u64, end: u64) -> Vec<u8> {
+    let digest = digest_from_hex(sha256);
+    let mut body = Vec::with_capacity((end - start + 1) as usize);
+    for index in start / ASSET_CHUNK_BYTES..=end / ASSET_CHUNK_BYTES {
+        let chunk = BLOB_CHUNK_STORAGE
+            .with(|storage| storage.borrow().get(&(digest, index as u32)))
+            .unwrap_or_default();
+        let chunk_start = index * ASSET_CHUNK_BYTES;
+        let from = start.saturating_sub(chunk_start) as usize;
+        let to = ((end - chunk_start + 1) as usize).min(chunk.len());
+        if from < to {
+            body.extend_from_slice(&chunk[from..to]);
+        }
+    }
+    body
+}
+
+// Parses a single `bytes=` range into inclusive offsets; `None` means the
+// header cannot be satisfied.
+fn parse_range(header: &str, size: u64) -> Option<(u64, u64)> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || size == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return None;
+            }
+            (size.saturating_sub(suffix), size - 1)
+        }
+        (start, "") => (start.parse().ok()?, size - 1),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(size - 1)),
+    };
+    if start > end {
+        return None;
+    }
+    Some((start, end))
+}
+
+fn http_error(status_code: u16, message: &str) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+        body: ByteBuf::from(message.as_bytes().to_vec()),
+        streaming_strategy: None,
+    }
+}
+
+// Serves `/assets/<id>[/<file name>][?token=<access token>]`. Partial
+// responses are capped at `MAX_HTTP_BODY_BYTES`; full responses for large
+// assets are streamed chunk by chunk.
+#[ic_cdk::query]
+fn http_request(request: HttpRequest) -> HttpResponse {
+    let is_head = request.method.eq_ignore_ascii_case("HEAD");
+    if !is_head && !request.method.eq_ignore_ascii_case("GET") {
+        return http_error(405, "Method not allowed");
+    }
+
+    let (path, query) = request.url.split_once('?').unwrap_or((&request.url, ""));
+    let asset = path
+        .strip_prefix("/assets/")
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|id| id.parse::<u64>().ok())
+        .and_then(get_asset);
+    let asset = match asset {
+        Some(asset) => asset,
+        None => return http_error(404, "Not found"),
+    };
+
+    let access_token = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .map(|token| token.to_string());
+    if !check_asset_token(&asset, access_token.as_deref()) {
+        return http_error(403, "Forbidden");
+    }
+
+    let header = |name: &str| {
+        request
+            .headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    };
+    let etag = format!("\"{}\"", asset.sha256);
+    let cache_control = match asset.access {
+        AssetAccess::Public => "public, max-age=31536000, immutable",
+        AssetAccess::EnrolledOnly => "private, no-store",
+    };
+    let mut headers = vec![
+        ("Content-Type".to_string(), asset.content_type.clone()),
+        ("Accept-Ranges".to_string(), "bytes".to_string()),
+        ("ETag".to_string(), etag.clone()),
+        ("Cache-Control".to_string(), cache_control.to_string()),
+    ];
+
+    if header("If-None-Match") == Some(etag.as_str()) {
+        return HttpResponse {
+            status_code: 304,
+            headers,
+            body: ByteBuf::new(),
+            streaming_strategy: None,
+        };
+    }
+
+    if let Some(range) = header("Range") {
+        let (start, end) = match parse_range(range, asset.size_bytes) {
+            Some((start, end)) if start < asset.size_bytes => {
+                (start, end.min(start + MAX_HTTP_BODY_BYTES - 1))
+            }
+            _ => {
+                let mut response = http_error(416, "Range not satisfiable");
+                response
+                    .headers
+                    .push(("Content-Range".to_string(), format!("bytes */{}", asset.size_bytes)));
+                return response;
+            }
+        };
+        headers.push((
+            "Content-Range".to_string(),
+            format!("bytes {}-{}/{}", start, end, asset.size_bytes),
+        ));
+        headers.push(("Content-Length".to_string(), (end - start + 1).to_string()));
+        let body = if is_head {
+            Vec::new()
+        } else {
+            read_blob_range(&asset.sha256, start, end)
+        };
+        return HttpResponse {
+            status_code: 206,
+            headers,
+            body: ByteBuf::from(body),
+            streaming_strategy: None,
+        };
+    }
+
+    headers.push(("Content-Length".to_string(), asset.size_bytes.to_string()));
+    if is_head {
+        return HttpResponse {
+            status_code: 200,
+            headers,
+            body: ByteBuf::new(),
+            streaming_strategy: None,
+        };
+    }
+
+    let first_end = asset.size_bytes.min(ASSET_CHUNK_BYTES) - 1;
+    let streaming_strategy = if asset.size_bytes > ASSET_CHUNK_BYTES {
+        Some(StreamingStrategy::Callback {
+            callback: StreamingCallback::new(
+                ic_cdk::id(),
+                "http_request_streaming_callback".to_string(),
+            ),
+            token: StreamingToken {
+                asset_id: asset.id,
+                chunk_index: 1,
+                access_token,
+            },
+        })
+    } else {
+        None
+    };
+
+    HttpResponse {
+        status_code: 200,
+        headers,
+        body: ByteBuf::from(read_blob_range(&asset.sha256, 0, first_end)),
+        streaming_strategy,
+    }
+}
+
+#[ic_cdk::query]
+fn http_request_streaming_callback(token: StreamingToken) -> StreamingCallbackHttpResponse {
+    // A token that no longer grants access, e.g. because it expired mid
+    // stream, ends the stream with an empty body.
+    let asset = get_asset(token.asset_id)
+        .filter(|asset| check_asset_token(asset, token.access_token.as_deref()));
+    let start = token.chunk_index as u64 * ASSET_CHUNK_BYTES;
+    let asset = match asset {
+        Some(asset) if start < asset.size_bytes => asset,
+        _ => {
+            return StreamingCallbackHttpResponse {
+                body: ByteBuf::new(),
+                token: None,
+            }
+        }
+    };
+    let end = (start + ASSET_CHUNK_BYTES).min(asset.size_bytes) - 1;
+
+    StreamingCallbackHttpResponse {
+        body: ByteBuf::from(read_blob_range(&asset.sha256, start, end)),
+        token: if end + 1 < asset.size_bytes {
+            Some(StreamingToken {
+                chunk_index: token.chunk_index + 1,
+                ..token
+            })
+        } else {
+            None
+        },
+    }
+}
+
+// Lesson Deadlines
+fn validate_deadline(deadline: &LessonDeadline) -> Result<(), String> {
+    match deadline {
+        LessonDeadline::Absolute { due_at } if *due_at == 0 => {
+            Err("Deadline must be a valid timestamp".to_string())
+        }
+        LessonDeadline::AfterEnrollment { secs } if *secs == 0 || *secs > MAX_RELATIVE_SCHEDULE_SECS => {
+            Err("Relative deadlines must be between 1 second and 365 days after enrollment".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+// A deadline set on the learner's cohort takes precedence over the lesson's
+// own deadline.
+fn lesson_due_at(lesson: &Lesson, enrollment: &Enrollment) -> Option<u64> {
+    let cohort_due_at = enrollment.cohort_id.and_then(get_cohort).and_then(|cohort| {
+        cohort
+            .deadlines
+            .iter()
+            .find(|deadline| deadline.lesson_id == lesson.id)
+            .map(|deadline| deadline.due_at)
+    });
+    if cohort_due_at.is_some() {
+        return cohort_due_at;
+    }
+
+    match lesson.deadline.as_ref()? {
+        LessonDeadline::Absolute { due_at } => Some(*due_at),
+        LessonDeadline::AfterEnrollment { secs } => Some(enrollment.enrolled_at + secs * NANOS_PER_SECOND),
+    }
+}
+
+#[ic_cdk::update]
+fn set_lesson_deadline(
+    course_id: u64,
+    lesson_id: u64,
+    deadline: Option<LessonDeadline>,
+) -> Result<Course, String> {
+    let caller = ic_cdk::caller();
+    let mut course = ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    if let Some(deadline) = &deadline {
+        validate_deadline(deadline)?;
+    }
+
+    let lesson = course
+        .lessons
+        .iter_mut()
+        .find(|lesson| lesson.id == lesson_id)
+        .ok_or_else(|| "Lesson not found".to_string())?;
+    lesson.deadline = deadline;
+    validate_lesson_schedule(lesson)?;
+
+    Ok(store_course_lessons(course))
+}
+
+// Deadlines of lessons the learner has not completed yet, soonest first,
+// including ones already missed.
+#[ic_cdk::query]
+fn get_my_upcoming_deadlines() -> Vec<UpcomingDeadline> {
+    let caller = ic_cdk::caller();
+    let now = time();
+
+    let mut deadlines: Vec<UpcomingDeadline> = Vec::new();
+    for enrollment in get_student_enrollments(caller) {
+        if enrollment.completed {
+            continue;
+        }
+        let course = match get_course(enrollment.course_id) {
+            Some(course) => course,
+            None => continue,
+        };
+        let progress = student_course_progress(caller, course.id);
+
+        for lesson in &course.lessons {
+            let due_at = match lesson_due_at(lesson, &enrollment) {
+                Some(due_at) => due_at,
+                None => continue,
+            };
+            if progress.iter().any(|p| p.lesson_id == lesson.id && p.completed) {
+                continue;
+            }
+            deadlines.push(UpcomingDeadline {
+                course_id: course.id,
+                course_title: course.title.clone(),
+                lesson_id: lesson.id,
+                lesson_title: lesson.title.clone(),
+                due_at,
+                overdue: due_at < now,
+            });
+        }
+    }
+
+    deadlines.sort_by_key(|deadline| deadline.due_at);
+    deadlines
+}
+
+fn push_notification(recipient: Principal, course_id: u64, kind: NotificationKind) -> Notification {
+    let notification = Notification {
+        id: get_next_id(),
+        recipient,
         course_id,
-        enrolled_at: time(),
-        completed: false,
-        completion_date: None,
+        kind,
+        created_at: time(),
+        read_at: None,
+    };
+
+    NOTIFICATION_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((recipient, notification.id), notification.clone());
+    });
+
+    notification
+}
+
+// Creates a reminder for every incomplete lesson falling due within
+// DEADLINE_REMINDER_LEAD_SECS. Each due time is reminded about once, so a
+// moved deadline produces a fresh reminder. A call handles at most
+// REMINDER_BATCH_SIZE enrollments after the stored cursor and returns true
+// once the scan has reached the last enrollment.
+fn send_deadline_reminders() -> bool {
+    let now = time();
+    let horizon = now + DEADLINE_REMINDER_LEAD_SECS * NANOS_PER_SECOND;
+
+    let cursor = REMINDER_CURSOR.with(|cursor| cursor.borrow().get(&0));
+    let batch: Vec<(u64, Principal)> = COURSE_STUDENT_INDEX.with(|index| {
+        let index = index.borrow();
+        let start = match cursor {
+            Some(cursor) => Bound::Excluded(cursor),
+            None => Bound::Unbounded,
+        };
+        index
+            .range((start, Bound::Unbounded))
+            .take(REMINDER_BATCH_SIZE)
+            .map(|(key, _)| key)
+            .collect()
+    });
+
+    let mut cached: Option<Course> = None;
+    for &(course_id, student) in &batch {
+        if cached.as_ref().map(|course| course.id) != Some(course_id) {
+            cached = get_course(course_id);
+        }
+        let course = match &cached {
+            Some(course) if course.lessons.iter().any(|lesson| lesson.deadline.is_some()) => course,
+            _ => continue,
+        };
+        let enrollment = match ENROLLMENT_STORAGE.with(|storage| storage.borrow().get(&(student, course_id))) {
+            Some(enrollment) if !enrollment.completed => enrollment,
+            _ => continue,
+        };
+        let progress = student_course_progress(student, course_id);
+
+        for lesson in &course.lessons {
+            let due_at = match lesson_due_at(lesson, &enrollment) {
+                Some(due_at) if due_at > now && due_at <= horizon => due_at,
+                _ => continue,
+            };
+            if progress.iter().any(|p| p.lesson_id == lesson.id && p.completed) {
+                continue;
+            }
+
+            let key = (student, course_id, lesson.id);
+            let reminded = DEADLINE_REMINDER_STORAGE.with(|storage| storage.borrow().get(&key));
+            if reminded == Some(due_at) {
+                continue;
+            }
+
+            push_notification(
+                student,
+                course_id,
+                NotificationKind::DeadlineReminder {
+                    lesson_id: lesson.id,
+                    due_at,
+                },
+            );
+            DEADLINE_REMINDER_STORAGE.with(|storage| {
+                storage.borrow_mut().insert(key, due_at);
+            });
+        }
+    }
+
+    let finished = batch.len() < REMINDER_BATCH_SIZE;
+    REMINDER_CURSOR.with(|cursor| {
+        let mut cursor = cursor.borrow_mut();
+        match batch.last() {
+            Some(last) if !finished => {
+                cursor.insert(0, *last);
+            }
+            _ => {
+                cursor.remove(&0);
+            }
+        }
+    });
+
+    finished
+}
+
+// Batches of one scan run back to back, each in its own message.
+fn run_reminder_batch() {
+    if !send_deadline_reminders() {
+        ic_cdk_timers::set_timer(Duration::ZERO, run_reminder_batch);
+    }
+}
+
+fn start_reminder_timer() {
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(REMINDER_SCAN_INTERVAL_SECS), run_reminder_batch);
+}
+
+#[ic_cdk::init]
+fn init() {
+    start_reminder_timer();
+}
+
+// Newest first.
+#[ic_cdk::query]
+fn get_my_notifications(unread_only: bool) -> Vec<Notification> {
+    let caller = ic_cdk::caller();
+
+    let mut notifications: Vec<Notification> = NOTIFICATION_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((caller, 0)..=(caller, u64::MAX))
+            .map(|(_, notification)| notification)
+            .filter(|notification| !unread_only || notification.read_at.is_none())
+            .collect()
+    });
+
+    notifications.reverse();
+    notifications
+}
+
+#[ic_cdk::update]
+fn mark_notification_read(notification_id: u64) -> Result<Notification, String> {
+    let caller = ic_cdk::caller();
+
+    NOTIFICATION_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        match storage.get(&(caller, notification_id)) {
+            Some(mut notification) => {
+                if notification.read_at.is_none() {
+                    notification.read_at = Some(time());
+                    storage.insert((caller, notification_id), notification.clone());
+                }
+                Ok(notification)
+            }
+            None => Err("Notification not found".to_string()),
+        }
+    })
+}
+
+// Course Announcements
+fn ensure_course_staff(course_id: u64, caller: Principal) -> Result<Course, String> {
+    let course = get_course(course_id).ok_or_else(|| "Course not found".to_string())?;
+    if course.educator != caller && staff_member(course_id, caller).is_none() {
+        return Err("Only course staff can manage announcements".to_string());
+    }
+    Ok(course)
+}
+
+fn validate_announcement(payload: &AnnouncementPayload) -> Result<(), String> {
+    if payload.title.trim().is_empty() {
+        return Err("Announcement title cannot be empty".to_string());
+    }
+    if payload.title.chars().count() > MAX_TITLE_CHARS {
+        return Err(format!("Announcement title cannot exceed {} characters", MAX_TITLE_CHARS));
+    }
+    if payload.body.trim().is_empty() {
+        return Err("Announcement body cannot be empty".to_string());
+    }
+    if payload.body.chars().count() > MAX_ANNOUNCEMENT_CHARS {
+        return Err(format!("Announcement body cannot exceed {} characters", MAX_ANNOUNCEMENT_CHARS));
+    }
+    Ok(())
+}
+
+fn get_announcement(course_id: u64, announcement_id: u64) -> Result<Announcement, String> {
+    ANNOUNCEMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(course_id, announcement_id)))
+        .ok_or_else(|| "Announcement not found".to_string())
+}
+
+fn announcement_notification(student: Principal, announcement_id: u64) -> Option<Notification> {
+    let notification_id = ANNOUNCEMENT_NOTIFICATION_INDEX
+        .with(|index| index.borrow().get(&(announcement_id, student)))?;
+    NOTIFICATION_STORAGE.with(|storage| storage.borrow().get(&(student, notification_id)))
+}
+
+// Notifies the next ANNOUNCEMENT_BATCH_SIZE enrolled learners and returns true
+// once the delivery has reached the last one or no longer exists.
+fn deliver_announcement_batch(announcement_id: u64) -> bool {
+    let mut delivery = match ANNOUNCEMENT_DELIVERY_STORAGE.with(|storage| storage.borrow().get(&announcement_id)) {
+        Some(delivery) => delivery,
+        None => return true,
+    };
+    let course_id = delivery.course_id;
+
+    let start = match delivery.last_student {
+        Some(student) => Bound::Excluded((course_id, student)),
+        None => Bound::Included((course_id, Principal::management_canister())),
+    };
+    let students: Vec<Principal> = COURSE_STUDENT_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((start, Bound::Unbounded))
+            .take_while(|((c_id, _), _)| *c_id == course_id)
+            .take(ANNOUNCEMENT_BATCH_SIZE)
+            .map(|((_, student), _)| student)
+            .collect()
+    });
+
+    for student in &students {
+        let notification =
+            push_notification(*student, course_id, NotificationKind::Announcement { announcement_id });
+        ANNOUNCEMENT_NOTIFICATION_INDEX.with(|index| {
+            index.borrow_mut().insert((announcement_id, *student), notification.id);
+        });
+    }
+
+    let finished = students.len() < ANNOUNCEMENT_BATCH_SIZE;
+    ANNOUNCEMENT_DELIVERY_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if finished {
+            storage.remove(&announcement_id);
+        } else {
+            delivery.last_student = students.last().copied();
+            storage.insert(announcement_id, delivery);
+        }
+    });
+
+    finished
+}
+
+fn schedule_announcement_delivery(announcement_id: u64) {
+    ic_cdk_timers::set_timer(Duration::ZERO, move || {
+        if !deliver_announcement_batch(announcement_id) {
+            schedule_announcement_delivery(announcement_id);
+        }
+    });
+}
+
+// Enrolled learners are notified in batches, each in its own message, right
+// after the announcement is posted. Learners who enroll later still see it in
+// the course listing.
+#[ic_cdk::update]
+fn create_announcement(course_id: u64, payload: AnnouncementPayload) -> Result<Announcement, String> {
+    let caller = ic_cdk::caller();
+    ensure_course_staff(course_id, caller)?;
+    validate_announcement(&payload)?;
+
+    let now = time();
+    let announcement = Announcement {
+        id: get_next_id(),
+        course_id,
+        author: caller,
+        title: payload.title.trim().to_string(),
+        body: payload.body,
+        pinned: payload.pinned,
+        created_at: now,
+        updated_at: now,
+    };
+
+    ANNOUNCEMENT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((course_id, announcement.id), announcement.clone());
+    });
+
+    ANNOUNCEMENT_DELIVERY_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(
+            announcement.id,
+            AnnouncementDelivery {
+                course_id,
+                last_student: None,
+            },
+        );
+    });
+    schedule_announcement_delivery(announcement.id);
+
+    Ok(announcement)
+}
+
+// Edits do not notify learners again.
+#[ic_cdk::update]
+fn update_announcement(
+    course_id: u64,
+    announcement_id: u64,
+    payload: AnnouncementPayload,
+) -> Result<Announcement, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_course_staff(course_id, caller)?;
+    let mut announcement = get_announcement(course_id, announcement_id)?;
+
+    if announcement.author != caller && !has_permission(&course, caller, &StaffPermission::EditContent) {
+        return Err("Only the author can edit this announcement".to_string());
+    }
+    validate_announcement(&payload)?;
+
+    announcement.title = payload.title.trim().to_string();
+    announcement.body = payload.body;
+    announcement.pinned = payload.pinned;
+    announcement.updated_at = time();
+
+    ANNOUNCEMENT_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((course_id, announcement_id), announcement.clone());
+    });
+
+    Ok(announcement)
+}
+
+// Stops a delivery still in progress and removes the notifications of the
+// course's current learners.
+#[ic_cdk::update]
+fn delete_announcement(course_id: u64, announcement_id: u64) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_course_staff(course_id, caller)?;
+    let announcement = get_announcement(course_id, announcement_id)?;
+
+    if announcement.author != caller && !has_permission(&course, caller, &StaffPermission::EditContent) {
+        return Err("Only the author can delete this announcement".to_string());
+    }
+
+    ANNOUNCEMENT_STORAGE.with(|storage| {
+        storage.borrow_mut().remove(&(course_id, announcement_id));
+    });
+    ANNOUNCEMENT_DELIVERY_STORAGE.with(|storage| {
+        storage.borrow_mut().remove(&announcement_id);
+    });
+
+    // Learners who have since left the course still hold a copy, so the
+    // notifications are found through the index rather than the roster.
+    let delivered: Vec<(Principal, u64)> = ANNOUNCEMENT_NOTIFICATION_INDEX.with(|index| {
+        index
+            .borrow()
+            .range((announcement_id, Principal::management_canister())..)
+            .take_while(|((a_id, _), _)| *a_id == announcement_id)
+            .map(|((_, student), notification_id)| (student, notification_id))
+            .collect()
+    });
+    for (student, notification_id) in delivered {
+        NOTIFICATION_STORAGE.with(|storage| {
+            storage.borrow_mut().remove(&(student, notification_id));
+        });
+        ANNOUNCEMENT_NOTIFICATION_INDEX.with(|index| {
+            index.borrow_mut().remove(&(announcement_id, student));
+        });
+    }
+
+    Ok("Announcement deleted successfully".to_string())
+}
+
+// Pinned announcements first, then newest first. Open to course staff and
+// enrolled learners.
+#[ic_cdk::query]
+fn get_course_announcements(course_id: u64, offset: u32, limit: u32) -> Result<AnnouncementPage, String> {
+    let caller = ic_cdk::caller();
+    let course = get_course(course_id).ok_or_else(|| "Course not found".to_string())?;
+    let is_staff = course.educator == caller || staff_member(course_id, caller).is_some();
+
+    let enrolled = ENROLLMENT_STORAGE.with(|storage| {
+        storage.borrow().contains_key(&(caller, course_id))
+    });
+    if !is_staff && !enrolled {
+        return Err("Not enrolled in this course".to_string());
+    }
+
+    let mut announcements: Vec<Announcement> = ANNOUNCEMENT_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .range((course_id, 0)..=(course_id, u64::MAX))
+            .map(|(_, announcement)| announcement)
+            .collect()
+    });
+    announcements.sort_by(|a, b| b.pinned.cmp(&a.pinned).then(b.created_at.cmp(&a.created_at)));
+
+    Ok(AnnouncementPage {
+        total: announcements.len() as u64,
+        announcements: announcements
+            .into_iter()
+            .skip(offset as usize)
+            .take(page_limit(limit))
+            .map(|announcement| {
+                let read_at = if is_staff {
+                    None
+                } else {
+                    announcement_notification(caller, announcement.id).and_then(|n| n.read_at)
+                };
+                AnnouncementView { announcement, read_at }
+            })
+            .collect(),
+    })
+}
+
+#[ic_cdk::update]
+fn mark_announcement_read(course_id: u64, announcement_id: u64) -> Result<Notification, String> {
+    let caller = ic_cdk::caller();
+    get_announcement(course_id, announcement_id)?;
+
+    let notification = announcement_notification(caller, announcement_id)
+        .ok_or_else(|| "This announcement was not sent to you".to_string())?;
+
+    mark_notification_read(notification.id)
+}
+
+#[ic_cdk::query]
+fn get_announcement_read_stats(course_id: u64, announcement_id: u64) -> Result<AnnouncementReadStats, String> {
+    let caller = ic_cdk::caller();
+    ensure_permission(course_id, caller, StaffPermission::ViewAnalytics)?;
+    get_announcement(course_id, announcement_id)?;
+
+    let read_times: Vec<Option<u64>> = course_student_ids(course_id)
+        .into_iter()
+        .filter_map(|student| announcement_notification(student, announcement_id))
+        .map(|notification| notification.read_at)
+        .collect();
+
+    Ok(AnnouncementReadStats {
+        announcement_id,
+        recipients: read_times.len() as u64,
+        read: read_times.iter().filter(|read_at| read_at.is_some()).count() as u64,
+    })
+}
+
+// Drip Scheduling
+fn validate_unlock(unlock: &LessonUnlock) -> Result<(), String> {
+    match unlock {
+        LessonUnlock::Absolute { unlocks_at } if *unlocks_at == 0 => {
+            Err("Unlock date must be a valid timestamp".to_string())
+        }
+        LessonUnlock::AfterEnrollment { secs } if *secs == 0 || *secs > MAX_RELATIVE_SCHEDULE_SECS => {
+            Err("Relative unlocks must be between 1 second and 365 days after enrollment".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+// Only schedules of the same kind can be compared up front; mixed ones
+// depend on each learner's enrollment date.
+fn validate_lesson_schedule(lesson: &Lesson) -> Result<(), String> {
+    let unlocks_too_late = match (&lesson.unlock, &lesson.deadline) {
+        (Some(LessonUnlock::Absolute { unlocks_at }), Some(LessonDeadline::Absolute { due_at })) => {
+            unlocks_at >= due_at
+        }
+        (
+            Some(LessonUnlock::AfterEnrollment { secs: unlock_secs }),
+            Some(LessonDeadline::AfterEnrollment { secs: due_secs }),
+        ) => unlock_secs >= due_secs,
+        _ => false,
     };
 
-    ENROLLMENT_STORAGE.with(|storage| {
-        storage.borrow_mut().insert(enrollment_key, enrollment.clone());
+    if unlocks_too_late {
+        return Err("A lesson must unlock before its deadline".to_string());
+    }
+    Ok(())
+}
+
+fn lesson_unlocks_at(lesson: &Lesson, enrollment: &Enrollment) -> Option<u64> {
+    match lesson.unlock.as_ref()? {
+        LessonUnlock::Absolute { unlocks_at } => Some(*unlocks_at),
+        LessonUnlock::AfterEnrollment { secs } => Some(enrollment.enrolled_at + secs * NANOS_PER_SECOND),
+    }
+}
+
+fn ensure_lesson_unlocked(lesson: &Lesson, enrollment: &Enrollment) -> Result<(), String> {
+    match lesson_unlocks_at(lesson, enrollment) {
+        Some(unlocks_at) if time() < unlocks_at => Err(format!("Lesson is locked until {}", unlocks_at)),
+        _ => Ok(()),
+    }
+}
+
+fn check_lesson_unlocked(student: Principal, course_id: u64, lesson_id: u64) -> Result<(), String> {
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(student, course_id)))
+        .ok_or_else(|| "Not enrolled in this course".to_string())?;
+    match get_course(course_id).and_then(|course| course.lessons.into_iter().find(|lesson| lesson.id == lesson_id)) {
+        Some(lesson) => ensure_lesson_unlocked(&lesson, &enrollment),
+        None => Ok(()),
+    }
+}
+
+#[ic_cdk::update]
+fn set_lesson_unlock(course_id: u64, lesson_id: u64, unlock: Option<LessonUnlock>) -> Result<Course, String> {
+    let caller = ic_cdk::caller();
+    let mut course = ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    if let Some(unlock) = &unlock {
+        validate_unlock(unlock)?;
+    }
+
+    let lesson = course
+        .lessons
+        .iter_mut()
+        .find(|lesson| lesson.id == lesson_id)
+        .ok_or_else(|| "Lesson not found".to_string())?;
+    lesson.unlock = unlock;
+    validate_lesson_schedule(lesson)?;
+
+    Ok(store_course_lessons(course))
+}
+
+// Course staff can open any lesson; learners only those already unlocked.
+// `locale` picks a translation the same way `get_localized_course` does.
+#[ic_cdk::query]
+fn get_lesson(course_id: u64, lesson_id: u64, locale: Option<String>) -> Result<Lesson, String> {
+    let caller = ic_cdk::caller();
+
+    let course = get_course(course_id).ok_or_else(|| "Course not found".to_string())?;
+    let is_staff = course.educator == caller || staff_member(course_id, caller).is_some();
+    let lesson = course
+        .lessons
+        .iter()
+        .find(|lesson| lesson.id == lesson_id)
+        .ok_or_else(|| "Lesson not found".to_string())?;
+
+    if !is_staff {
+        let enrollment = ENROLLMENT_STORAGE
+            .with(|storage| storage.borrow().get(&(caller, course_id)))
+            .ok_or_else(|| "Not enrolled in this course".to_string())?;
+        ensure_lesson_unlocked(lesson, &enrollment)?;
+    }
+
+    localize_course(course, locale.as_deref())
+        .lessons
+        .into_iter()
+        .find(|lesson| lesson.id == lesson_id)
+        .ok_or_else(|| "Lesson not found".to_string())
+}
+
+// Scheduled lessons across the learner's active enrollments, in unlock order.
+#[ic_cdk::query]
+fn get_my_unlock_calendar() -> Vec<UnlockCalendarEntry> {
+    let caller = ic_cdk::caller();
+    let now = time();
+
+    let mut calendar: Vec<UnlockCalendarEntry> = Vec::new();
+    for enrollment in get_student_enrollments(caller) {
+        if enrollment.completed {
+            continue;
+        }
+        let course = match get_course(enrollment.course_id) {
+            Some(course) => course,
+            None => continue,
+        };
+
+        for lesson in &course.lessons {
+            if let Some(unlocks_at) = lesson_unlocks_at(lesson, &enrollment) {
+                calendar.push(UnlockCalendarEntry {
+                    course_id: course.id,
+                    course_title: course.title.clone(),
+                    lesson_id: lesson.id,
+                    lesson_title: lesson.title.clone(),
+                    order: lesson.order,
+                    unlocks_at,
+                    unlocked: unlocks_at <= now,
+                });
+            }
+        }
+    }
+
+    calendar.sort_by_key(|entry| (entry.unlocks_at, entry.course_id, entry.order));
+    calendar
+}
+
+// Lesson Branching
+fn validate_branch(
+    lessons: &[Lesson],
+    lesson_id: u64,
+    branch: &LessonBranch,
+    exam_exists: impl Fn(u64) -> bool,
+) -> Result<(), String> {
+    if branch.to_lesson_id == lesson_id {
+        return Err("A lesson cannot branch to itself".to_string());
+    }
+    if !lessons.iter().any(|lesson| lesson.id == branch.to_lesson_id) {
+        return Err(format!("Branch target lesson {} not found", branch.to_lesson_id));
+    }
+    match branch.condition {
+        BranchCondition::Always => Ok(()),
+        BranchCondition::ScoreBelow { exam_id, percent } | BranchCondition::ScoreAtLeast { exam_id, percent } => {
+            if !exam_exists(exam_id) {
+                return Err(format!("Branch condition refers to unknown exam {}", exam_id));
+            }
+            if percent > 100 {
+                return Err("Score thresholds must be a percentage between 0 and 100".to_string());
+            }
+            Ok(())
+        }
+    }
+}
+
+// Replaces all outgoing branches of a lesson. Loops are allowed so that a
+// remedial lesson can lead back to the quiz it follows.
+#[ic_cdk::update]
+fn set_lesson_branches(course_id: u64, lesson_id: u64, branches: Vec<LessonBranch>) -> Result<Course, String> {
+    let caller = ic_cdk::caller();
+    let mut course = ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    if branches.len() > MAX_LESSON_BRANCHES {
+        return Err(format!("A lesson can have at most {} branches", MAX_LESSON_BRANCHES));
+    }
+    if !course.lessons.iter().any(|lesson| lesson.id == lesson_id) {
+        return Err("Lesson not found".to_string());
+    }
+
+    let course_exams: Vec<u64> = get_course_exams(course_id).iter().map(|exam| exam.id).collect();
+    for branch in &branches {
+        validate_branch(&course.lessons, lesson_id, branch, |exam_id| course_exams.contains(&exam_id))?;
+    }
+
+    if let Some(lesson) = course.lessons.iter_mut().find(|lesson| lesson.id == lesson_id) {
+        lesson.branches = branches;
+    }
+
+    Ok(store_course_lessons(course))
+}
+
+fn best_exam_percent(student: Principal, exam_id: u64) -> Option<f64> {
+    student_exam_attempts(student, exam_id)
+        .iter()
+        .filter(|a| a.status == ExamAttemptStatus::Submitted && a.max_score > 0)
+        .map(|a| a.score as f64 * 100.0 / a.max_score as f64)
+        .reduce(f64::max)
+}
+
+fn branch_matches(student: Principal, condition: &BranchCondition) -> bool {
+    match condition {
+        BranchCondition::Always => true,
+        BranchCondition::ScoreBelow { exam_id, percent } => {
+            best_exam_percent(student, *exam_id).is_some_and(|score| score < *percent as f64)
+        }
+        BranchCondition::ScoreAtLeast { exam_id, percent } => {
+            best_exam_percent(student, *exam_id).is_some_and(|score| score >= *percent as f64)
+        }
+    }
+}
+
+// Walks the lesson graph from the first lesson, passing over completed
+// lessons along the first matching branch out of each. Reaching a lesson a
+// second time stops the walk there. `None` means the learner's path through
+// the course is finished.
+fn adaptive_next_lesson(enrollment: &Enrollment, course: &Course) -> Option<LessonRecommendation> {
+    let mut lessons: Vec<&Lesson> = course.lessons.iter().collect();
+    lessons.sort_by_key(|lesson| lesson.order);
+    let progress = student_course_progress(enrollment.student, course.id);
+
+    let mut current = *lessons.first()?;
+    let mut via_branch = false;
+    let mut visited: Vec<u64> = Vec::new();
+
+    loop {
+        let completed = progress.iter().any(|p| p.lesson_id == current.id && p.completed);
+        let revisit = visited.contains(&current.id);
+        if !completed || revisit {
+            return Some(LessonRecommendation {
+                lesson_id: current.id,
+                title: current.title.clone(),
+                order: current.order,
+                via_branch,
+                revisit,
+                locked_until: lesson_unlocks_at(current, enrollment).filter(|unlocks_at| *unlocks_at > time()),
+            });
+        }
+        visited.push(current.id);
+
+        let branch = current
+            .branches
+            .iter()
+            .find(|branch| branch_matches(enrollment.student, &branch.condition));
+        via_branch = branch.is_some();
+        current = match branch {
+            Some(branch) => *lessons.iter().find(|lesson| lesson.id == branch.to_lesson_id)?,
+            None => {
+                let position = lessons.iter().position(|lesson| lesson.id == current.id)?;
+                *lessons.get(position + 1)?
+            }
+        };
+    }
+}
+
+#[ic_cdk::query]
+fn get_my_next_lesson(course_id: u64) -> Result<Option<LessonRecommendation>, String> {
+    let caller = ic_cdk::caller();
+
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&(caller, course_id)))
+        .ok_or_else(|| "Not enrolled in this course".to_string())?;
+    let course = get_course(course_id).ok_or_else(|| "Course not found".to_string())?;
+
+    Ok(adaptive_next_lesson(&enrollment, &course))
+}
+
+// Course Bundles
+// The bundle includes the question bank with its answer keys, so exporting
+// requires content rights.
+#[ic_cdk::query]
+fn export_course(course_id: u64) -> Result<CourseBundle, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+
+    let questions = course_bank_questions(course_id)
+        .into_iter()
+        .map(|question| BankQuestionPayload {
+            topic: question.topic,
+            difficulty: question.difficulty,
+            prompt: question.prompt,
+            options: question.options,
+            correct_option: question.correct_option,
+            points: question.points,
+        })
+        .collect();
+
+    let exams = get_course_exams(course_id)
+        .into_iter()
+        .map(|exam| BundleExam {
+            id: exam.id,
+            title: exam.title,
+            kind: exam.kind,
+            sections: exam.sections,
+            time_limit_secs: exam.time_limit_secs,
+            max_attempts: exam.max_attempts,
+            passing_score: exam.passing_score,
+        })
+        .collect();
+
+    let assets = get_course_assets(course_id)
+        .into_iter()
+        .map(|asset| BundleAsset {
+            id: asset.id,
+            file_name: asset.file_name,
+            content_type: asset.content_type,
+            size_bytes: asset.size_bytes,
+            sha256: asset.sha256,
+            access: asset.access,
+        })
+        .collect();
+
+    Ok(CourseBundle {
+        format_version: COURSE_BUNDLE_VERSION,
+        exported_at: time(),
+        manifest: CourseManifest {
+            source_course_id: course.id,
+            title: course.title,
+            description: course.description,
+            level: course.level,
+            image: course.image,
+            tags: course.tags,
+            enrollment_policy: course.enrollment_policy,
+            price: course.price,
+            reviews_require_completion: course.reviews_require_completion,
+            default_locale: Some(course.default_locale),
+        },
+        lessons: course.lessons,
+        questions,
+        exams,
+        assets,
+    })
+}
+
+fn record_issue<T>(issues: &mut Vec<BundleIssue>, path: String, result: Result<T, String>) -> Option<T> {
+    result.map_err(|message| issues.push(BundleIssue { path, message })).ok()
+}
+
+// Sanitizes every lesson the same way `add_lesson` does. Quiz blocks keep
+// their bundle exam ids until the exams are stored.
+fn prepare_bundle_lessons(bundle: &CourseBundle, issues: &mut Vec<BundleIssue>) -> Vec<Lesson> {
+    let mut lessons = Vec::with_capacity(bundle.lessons.len());
+
+    for (index, lesson) in bundle.lessons.iter().enumerate() {
+        let path = format!("lessons[{}]", index);
+
+        if lesson.id == 0 {
+            issues.push(BundleIssue {
+                path: format!("{}.id", path),
+                message: "Lesson ids must be greater than zero".to_string(),
+            });
+        } else if bundle.lessons[..index].iter().any(|other| other.id == lesson.id) {
+            issues.push(BundleIssue {
+                path: format!("{}.id", path),
+                message: format!("Lesson id {} is used more than once", lesson.id),
+            });
+        }
+
+        let title = record_issue(
+            issues,
+            format!("{}.title", path),
+            validate_title(&lesson.title, "Lesson title"),
+        );
+
+        if lesson.content.is_empty() || lesson.content.len() > MAX_LESSON_BLOCKS {
+            issues.push(BundleIssue {
+                path: format!("{}.content", path),
+                message: format!("A lesson needs between 1 and {} content blocks", MAX_LESSON_BLOCKS),
+            });
+        }
+
+        let mut content = Vec::with_capacity(lesson.content.len());
+        let mut duration_secs = 0;
+        for (block_index, block) in lesson.content.iter().enumerate() {
+            let prepared = match block {
+                LessonContent::Quiz { exam_id } => bundle
+                    .exams
+                    .iter()
+                    .find(|exam| exam.id == *exam_id)
+                    .map(|exam| (block.clone(), exam.time_limit_secs))
+                    .ok_or_else(|| format!("Quiz refers to exam {} which is not in the bundle", exam_id)),
+                _ => prepare_content_block(0, block.clone()),
+            };
+            let block_path = format!("{}.content[{}]", path, block_index);
+            if let Some((block, secs)) = record_issue(issues, block_path, prepared) {
+                content.push(block);
+                duration_secs += secs;
+            }
+        }
+
+        if let Some(deadline) = &lesson.deadline {
+            record_issue(issues, format!("{}.deadline", path), validate_deadline(deadline));
+        }
+        if let Some(unlock) = &lesson.unlock {
+            record_issue(issues, format!("{}.unlock", path), validate_unlock(unlock));
+        }
+        record_issue(issues, path.clone(), validate_lesson_schedule(lesson));
+
+        if lesson.branches.len() > MAX_LESSON_BRANCHES {
+            issues.push(BundleIssue {
+                path: format!("{}.branches", path),
+                message: format!("A lesson can have at most {} branches", MAX_LESSON_BRANCHES),
+            });
+        }
+        for (branch_index, branch) in lesson.branches.iter().enumerate() {
+            let checked = validate_branch(&bundle.lessons, lesson.id, branch, |exam_id| {
+                bundle.exams.iter().any(|exam| exam.id == exam_id)
+            });
+            record_issue(issues, format!("{}.branches[{}]", path, branch_index), checked);
+        }
+
+        lessons.push(Lesson {
+            id: lesson.id,
+            title: title.unwrap_or_default(),
+            content,
+            duration_secs,
+            order: lesson.order,
+            deadline: lesson.deadline.clone(),
+            unlock: lesson.unlock.clone(),
+            branches: lesson.branches.clone(),
+        });
+    }
+
+    lessons
+}
+
+// Exams are checked against the bundle's own lessons and question bank,
+// including whether the bank can fill every section.
+fn validate_bundle_exams(bundle: &CourseBundle, lessons: &[Lesson], issues: &mut Vec<BundleIssue>) {
+    let bank: Vec<BankQuestion> = bundle
+        .questions
+        .iter()
+        .enumerate()
+        .map(|(index, question)| BankQuestion {
+            id: index as u64,
+            course_id: 0,
+            topic: question.topic.trim().to_string(),
+            difficulty: question.difficulty.clone(),
+            prompt: question.prompt.clone(),
+            options: question.options.clone(),
+            correct_option: question.correct_option,
+            points: question.points,
+            created_at: 0,
+            updated_at: 0,
+        })
+        .collect();
+
+    for (index, exam) in bundle.exams.iter().enumerate() {
+        let path = format!("exams[{}]", index);
+
+        if bundle.exams[..index].iter().any(|other| other.id == exam.id) {
+            issues.push(BundleIssue {
+                path: format!("{}.id", path),
+                message: format!("Exam id {} is used more than once", exam.id),
+            });
+        }
+
+        let payload = CreateExamPayload {
+            course_id: 0,
+            title: exam.title.clone(),
+            kind: exam.kind.clone(),
+            sections: exam.sections.clone(),
+            time_limit_secs: exam.time_limit_secs,
+            max_attempts: exam.max_attempts,
+            passing_score: exam.passing_score,
+        };
+        if record_issue(issues, path.clone(), validate_exam_payload(lessons, &payload)).is_none() {
+            continue;
+        }
+
+        let draft = Exam {
+            id: exam.id,
+            course_id: 0,
+            title: payload.title,
+            kind: payload.kind,
+            sections: payload.sections,
+            time_limit_secs: payload.time_limit_secs,
+            max_attempts: payload.max_attempts,
+            passing_score: payload.passing_score,
+            created_at: 0,
+            updated_at: 0,
+        };
+        let drawn = draw_exam_questions(&draft, &bank, &mut ExamRng::from_seed(&[]));
+        record_issue(issues, format!("{}.sections", path), drawn);
+    }
+}
+
+// A content hash is public through get_course_assets, so it proves nothing on
+// its own. Only content the caller already reaches through an asset they own
+// or an asset of a course they can edit may be linked by an import.
+fn linkable_hashes(caller: Principal) -> Vec<String> {
+    let mut hashes: Vec<String> = ASSET_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, asset)| {
+                if asset.owner == caller
+                    || ensure_permission(asset.course_id, caller, StaffPermission::EditContent).is_ok()
+                {
+                    Some(asset.sha256)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    });
+    hashes.sort();
+    hashes.dedup();
+    hashes
+}
+
+// Linking stored content counts towards the importer's quota like an upload.
+fn validate_bundle_assets(
+    bundle: &CourseBundle,
+    owner: Principal,
+    linkable: &[String],
+    issues: &mut Vec<BundleIssue>,
+) {
+    for (index, asset) in bundle.assets.iter().enumerate() {
+        let path = format!("assets[{}]", index);
+
+        if bundle.assets[..index].iter().any(|other| other.id == asset.id) {
+            issues.push(BundleIssue {
+                path: format!("{}.id", path),
+                message: format!("Asset id {} is used more than once", asset.id),
+            });
+        }
+        record_issue(issues, format!("{}.file_name", path), validate_asset_file_name(&asset.file_name));
+
+        match asset_type_limit(&asset.content_type) {
+            Some(limit) if asset.size_bytes == 0 || asset.size_bytes > limit => issues.push(BundleIssue {
+                path: format!("{}.size_bytes", path),
+                message: format!("{} files must be between 1 and {} bytes", asset.content_type, limit),
+            }),
+            Some(_) => {}
+            None => issues.push(BundleIssue {
+                path: format!("{}.content_type", path),
+                message: format!("Unsupported content type '{}'", asset.content_type),
+            }),
+        }
+
+        if asset.sha256.len() != 64 || !asset.sha256.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+            issues.push(BundleIssue {
+                path: format!("{}.sha256", path),
+                message: "Content hash must be 64 lowercase hex characters".to_string(),
+            });
+        }
+    }
+
+    let owned: Vec<String> = owner_assets(owner).into_iter().map(|asset| asset.sha256).collect();
+    let mut linked: Vec<(String, u64)> = bundle
+        .assets
+        .iter()
+        .filter(|asset| linkable.contains(&asset.sha256) && !owned.contains(&asset.sha256))
+        .filter_map(|asset| {
+            BLOB_STORAGE
+                .with(|storage| storage.borrow().get(&asset.sha256))
+                .map(|blob| (asset.sha256.clone(), blob.size_bytes))
+        })
+        .collect();
+    linked.sort();
+    linked.dedup();
+
+    let usage = compute_asset_usage(owner);
+    let added_bytes: u64 = linked.iter().map(|(_, size)| size).sum();
+    if usage.used_bytes + usage.reserved_bytes + added_bytes > usage.quota_bytes {
+        issues.push(BundleIssue {
+            path: "assets".to_string(),
+            message: "Asset storage quota exceeded".to_string(),
+        });
+    }
+}
+
+// Validates the whole bundle and reports every problem found. Unless this is
+// a dry run and the bundle is clean, the course is created unpublished and
+// owned by the caller, with its question bank, exams and any assets whose
+// content the caller can already reach here.
+#[ic_cdk::update]
+fn import_course(bundle: CourseBundle, dry_run: bool) -> Result<CourseImportReport, String> {
+    let caller = ic_cdk::caller();
+
+    if caller == Principal::anonymous() {
+        return Err("Anonymous users cannot import courses".to_string());
+    }
+
+    let mut report = CourseImportReport {
+        dry_run,
+        issues: Vec::new(),
+        course: None,
+        exam_ids: Vec::new(),
+        asset_ids: Vec::new(),
+        missing_assets: Vec::new(),
+    };
+
+    if bundle.format_version != COURSE_BUNDLE_VERSION {
+        report.issues.push(BundleIssue {
+            path: "format_version".to_string(),
+            message: format!(
+                "Unsupported bundle format version {}; expected {}",
+                bundle.format_version, COURSE_BUNDLE_VERSION
+            ),
+        });
+        return Ok(report);
+    }
+
+    let title = record_issue(
+        &mut report.issues,
+        "manifest.title".to_string(),
+        validate_title(&bundle.manifest.title, "Course title"),
+    );
+    let default_locale = match &bundle.manifest.default_locale {
+        Some(locale) => record_issue(
+            &mut report.issues,
+            "manifest.default_locale".to_string(),
+            normalize_locale(locale),
+        ),
+        None => Some(DEFAULT_LOCALE.to_string()),
+    };
+    let mut lessons = prepare_bundle_lessons(&bundle, &mut report.issues);
+    for (index, question) in bundle.questions.iter().enumerate() {
+        record_issue(&mut report.issues, format!("questions[{}]", index), validate_bank_question(question));
+    }
+    validate_bundle_exams(&bundle, &lessons, &mut report.issues);
+    let linkable = linkable_hashes(caller);
+    validate_bundle_assets(&bundle, caller, &linkable, &mut report.issues);
+
+    report.missing_assets = bundle
+        .assets
+        .iter()
+        .filter(|asset| !linkable.contains(&asset.sha256))
+        .map(|asset| asset.id)
+        .collect();
+
+    if dry_run || !report.issues.is_empty() {
+        return Ok(report);
+    }
+
+    let course_id = get_next_id();
+    report.exam_ids = bundle
+        .exams
+        .iter()
+        .map(|exam| BundleIdMapping {
+            source_id: exam.id,
+            id: get_next_id(),
+        })
+        .collect();
+
+    let imported_exam_id = |source_id: u64| {
+        report
+            .exam_ids
+            .iter()
+            .find(|mapping| mapping.source_id == source_id)
+            .map(|mapping| mapping.id)
+            .unwrap_or(source_id)
+    };
+    for lesson in lessons.iter_mut() {
+        for block in lesson.content.iter_mut() {
+            if let LessonContent::Quiz { exam_id } = block {
+                *exam_id = imported_exam_id(*exam_id);
+            }
+        }
+        for branch in lesson.branches.iter_mut() {
+            match &mut branch.condition {
+                BranchCondition::ScoreBelow { exam_id, .. } | BranchCondition::ScoreAtLeast { exam_id, .. } => {
+                    *exam_id = imported_exam_id(*exam_id);
+                }
+                BranchCondition::Always => {}
+            }
+        }
+    }
+
+    let manifest = bundle.manifest;
+    let course = Course {
+        id: course_id,
+        title: title.unwrap_or_default(),
+        description: sanitize_text(&manifest.description),
+        educator: caller,
+        duration_secs: lessons.iter().map(|lesson| lesson.duration_secs).sum(),
+        lessons,
+        level: manifest.level,
+        image: manifest.image,
+        tags: normalize_tags(&manifest.tags),
+        prerequisites: Vec::new(),
+        published: false,
+        enrollment_policy: manifest.enrollment_policy,
+        price: manifest.price,
+        reviews_require_completion: manifest.reviews_require_completion,
+        default_locale: default_locale.unwrap_or_default(),
+        rating_total: 0,
+        rating_count: 0,
+        average_rating: 0.0,
+        enrolled_students: 0,
+        created_at: time(),
+        updated_at: time(),
+    };
+
+    COURSE_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(course_id, course.clone());
+    });
+    EDUCATOR_COURSE_INDEX.with(|index| {
+        index.borrow_mut().insert((caller, course_id), ());
     });
+    add_to_catalog(&course);
+
+    for question in bundle.questions {
+        let question_id = get_next_id();
+        let question = BankQuestion {
+            id: question_id,
+            course_id,
+            topic: question.topic.trim().to_string(),
+            difficulty: question.difficulty,
+            prompt: question.prompt,
+            options: question.options,
+            correct_option: question.correct_option,
+            points: question.points,
+            created_at: time(),
+            updated_at: time(),
+        };
+        QUESTION_BANK_STORAGE.with(|storage| {
+            storage.borrow_mut().insert((course_id, question_id), question);
+        });
+    }
+
+    for (exam, mapping) in bundle.exams.into_iter().zip(&report.exam_ids) {
+        let exam = Exam {
+            id: mapping.id,
+            course_id,
+            title: exam.title,
+            kind: exam.kind,
+            sections: exam.sections,
+            time_limit_secs: exam.time_limit_secs,
+            max_attempts: exam.max_attempts,
+            passing_score: exam.passing_score,
+            created_at: time(),
+            updated_at: time(),
+        };
+        EXAM_STORAGE.with(|storage| {
+            storage.borrow_mut().insert(exam.id, exam);
+        });
+    }
+
+    for asset in bundle.assets {
+        if !linkable.contains(&asset.sha256) {
+            continue;
+        }
+        let mut blob = match BLOB_STORAGE.with(|storage| storage.borrow().get(&asset.sha256)) {
+            Some(blob) => blob,
+            None => continue,
+        };
+        blob.ref_count += 1;
+        let size_bytes = blob.size_bytes;
+        BLOB_STORAGE.with(|storage| {
+            storage.borrow_mut().insert(asset.sha256.clone(), blob);
+        });
+
+        let imported = Asset {
+            id: get_next_id(),
+            course_id,
+            owner: caller,
+            file_name: validate_asset_file_name(&asset.file_name).unwrap_or_default(),
+            content_type: asset.content_type,
+            size_bytes,
+            sha256: asset.sha256,
+            access: asset.access,
+            created_at: time(),
+        };
+        report.asset_ids.push(BundleIdMapping {
+            source_id: asset.id,
+            id: imported.id,
+        });
+        ASSET_STORAGE.with(|storage| {
+            storage.borrow_mut().insert(imported.id, imported);
+        });
+    }
+
+    report.course = Some(course);
+    Ok(report)
+}
+
+// Course Localization
+// Accepts BCP 47 style tags such as "pt_br" or "zh-hant-TW" and returns them
+// in canonical case: "pt-BR", "zh-Hant-TW".
+fn normalize_locale(locale: &str) -> Result<String, String> {
+    let subtags: Vec<&str> = locale.trim().split(['-', '_']).collect();
+    let language = subtags[0];
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("Invalid locale \"{}\"", locale));
+    }
+
+    let mut normalized = vec![language.to_ascii_lowercase()];
+    for subtag in &subtags[1..] {
+        if !(2..=8).contains(&subtag.len()) || !subtag.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("Invalid locale \"{}\"", locale));
+        }
+        let subtag = if subtag.len() == 2 {
+            subtag.to_ascii_uppercase()
+        } else if subtag.len() == 4 {
+            let mut chars = subtag.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase()).unwrap_or_default();
+            format!("{}{}", first, chars.as_str().to_ascii_lowercase())
+        } else {
+            subtag.to_ascii_lowercase()
+        };
+        normalized.push(subtag);
+    }
+    Ok(normalized.join("-"))
+}
+
+// The locale itself followed by each shorter prefix: "zh-Hant-TW", "zh-Hant", "zh".
+fn locale_fallbacks(locale: &str) -> Vec<String> {
+    let subtags: Vec<&str> = locale.split('-').collect();
+    (1..=subtags.len()).rev().map(|len| subtags[..len].join("-")).collect()
+}
 
-    // Update enrolled students count
-    COURSE_STORAGE.with(|storage| {
+fn course_translations(course_id: u64) -> Vec<CourseTranslation> {
+    COURSE_TRANSLATION_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .get(&course_id)
+            .map(|entry| entry.translations)
+            .unwrap_or_default()
+    })
+}
+
+fn store_course_translations(course_id: u64, translations: Vec<CourseTranslation>) {
+    COURSE_TRANSLATION_STORAGE.with(|storage| {
         let mut storage = storage.borrow_mut();
-        if let Some(mut course) = storage.get(&course_id) {
-            course.enrolled_students += 1;
-            storage.insert(course_id, course);
+        if translations.is_empty() {
+            storage.remove(&course_id);
+        } else {
+            storage.insert(course_id, CourseTranslations { translations });
         }
     });
+}
+
+// Translated content has to keep the lesson's block layout, so quizzes and
+// progress stay attached to the same blocks in every locale.
+fn same_block_layout(original: &[LessonContent], translated: &[LessonContent]) -> bool {
+    original.len() == translated.len()
+        && original.iter().zip(translated).all(|(a, b)| match (a, b) {
+            (LessonContent::Quiz { exam_id: a }, LessonContent::Quiz { exam_id: b }) => a == b,
+            _ => std::mem::discriminant(a) == std::mem::discriminant(b),
+        })
+}
+
+fn prepare_translation(
+    course: &Course,
+    caller: Principal,
+    payload: CourseTranslationPayload,
+) -> Result<CourseTranslation, String> {
+    let locale = normalize_locale(&payload.locale)?;
+    if locale == course.default_locale {
+        return Err("Update the course itself to change its default locale content".to_string());
+    }
 
-    Ok(enrollment)
+    let title = if payload.title.trim().is_empty() {
+        String::new()
+    } else {
+        validate_title(&payload.title, "Course title")?
+    };
+
+    let mut lessons: Vec<LessonTranslation> = Vec::new();
+    for lesson_translation in payload.lessons {
+        let lesson = course
+            .lessons
+            .iter()
+            .find(|lesson| lesson.id == lesson_translation.lesson_id)
+            .ok_or_else(|| format!("Lesson {} not found", lesson_translation.lesson_id))?;
+        if lessons.iter().any(|l| l.lesson_id == lesson.id) {
+            return Err(format!("Lesson {} is translated more than once", lesson.id));
+        }
+
+        let lesson_title = if lesson_translation.title.trim().is_empty() {
+            String::new()
+        } else {
+            validate_title(&lesson_translation.title, "Lesson title")?
+        };
+        if !lesson_translation.content.is_empty() && !same_block_layout(&lesson.content, &lesson_translation.content) {
+            return Err(format!(
+                "Translated content for lesson {} must have the same blocks in the same order",
+                lesson.id
+            ));
+        }
+        let content = lesson_translation
+            .content
+            .into_iter()
+            .map(|block| prepare_content_block(course.id, block).map(|(block, _)| block))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        lessons.push(LessonTranslation {
+            lesson_id: lesson.id,
+            title: lesson_title,
+            content,
+        });
+    }
+
+    Ok(CourseTranslation {
+        course_id: course.id,
+        locale,
+        title,
+        description: sanitize_text(&payload.description),
+        lessons,
+        updated_by: caller,
+        updated_at: time(),
+    })
 }
 
+// Adds or replaces the translation for one locale.
 #[ic_cdk::update]
-fn complete_lesson(course_id: u64, lesson_id: u64) -> Result<LessonProgress, String> {
+fn set_course_translation(course_id: u64, payload: CourseTranslationPayload) -> Result<CourseTranslation, String> {
     let caller = ic_cdk::caller();
-    
-    // Check if enrolled in course
-    let enrollment_key = (caller, course_id);
-    let is_enrolled = ENROLLMENT_STORAGE.with(|storage| {
-        storage.borrow().contains_key(&enrollment_key)
-    });
+    let course = ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+    let translation = prepare_translation(&course, caller, payload)?;
 
-    if !is_enrolled {
-        return Err("Not enrolled in this course".to_string());
+    let mut translations = course_translations(course_id);
+    translations.retain(|existing| existing.locale != translation.locale);
+    translations.push(translation.clone());
+    translations.sort_by(|a, b| a.locale.cmp(&b.locale));
+
+    remove_from_catalog(&course);
+    store_course_translations(course_id, translations);
+    add_to_catalog(&course);
+
+    Ok(translation)
+}
+
+#[ic_cdk::update]
+fn remove_course_translation(course_id: u64, locale: String) -> Result<String, String> {
+    let caller = ic_cdk::caller();
+    let course = ensure_permission(course_id, caller, StaffPermission::EditContent)?;
+    let locale = normalize_locale(&locale)?;
+
+    let mut translations = course_translations(course_id);
+    let count = translations.len();
+    translations.retain(|translation| translation.locale != locale);
+    if translations.len() == count {
+        return Err("Translation not found".to_string());
     }
 
-    let progress_key = (caller, course_id, lesson_id);
-    let progress = LessonProgress {
-        student: caller,
-        course_id,
-        lesson_id,
-        completed: true,
-        completed_at: Some(time()),
+    remove_from_catalog(&course);
+    store_course_translations(course_id, translations);
+    add_to_catalog(&course);
+
+    Ok(format!("Translation \"{}\" removed successfully", locale))
+}
+
+// As with `get_course`, translated lesson content is only returned to course
+// staff; learners read it through `get_lesson`.
+#[ic_cdk::query]
+fn get_course_translation(course_id: u64, locale: String) -> Option<CourseTranslation> {
+    let caller = ic_cdk::caller();
+    let course = get_course(course_id)?;
+    let is_staff = course.educator == caller || staff_member(course_id, caller).is_some();
+
+    let locale = normalize_locale(&locale).ok()?;
+    let mut translation = course_translations(course_id)
+        .into_iter()
+        .find(|translation| translation.locale == locale)?;
+    if !is_staff {
+        for lesson in translation.lessons.iter_mut() {
+            lesson.content.clear();
+        }
+    }
+    Some(translation)
+}
+
+// The default locale comes first.
+#[ic_cdk::query]
+fn get_course_locales(course_id: u64) -> Result<Vec<String>, String> {
+    let course = get_course(course_id).ok_or_else(|| "Course not found".to_string())?;
+
+    let mut locales = vec![course.default_locale];
+    locales.extend(course_translations(course_id).into_iter().map(|translation| translation.locale));
+    Ok(locales)
+}
+
+// Picks the closest translation to the preferred locale, e.g. "pt" for
+// "pt-BR", and fills in whatever it leaves out from the default locale.
+// Unknown or invalid locales return the course unchanged.
+fn localize_course(mut course: Course, locale: Option<&str>) -> Course {
+    let preferred = match locale.map(normalize_locale) {
+        Some(Ok(locale)) => locale,
+        _ => return course,
     };
 
-    PROGRESS_STORAGE.with(|storage| {
-        storage.borrow_mut().insert(progress_key, progress.clone());
+    let mut translations = course_translations(course.id);
+    let index = locale_fallbacks(&preferred)
+        .into_iter()
+        .take_while(|candidate| *candidate != course.default_locale)
+        .find_map(|candidate| translations.iter().position(|translation| translation.locale == candidate));
+    let translation = match index {
+        Some(index) => translations.swap_remove(index),
+        None => return course,
+    };
+
+    if !translation.title.is_empty() {
+        course.title = translation.title;
+    }
+    if !translation.description.is_empty() {
+        course.description = translation.description;
+    }
+    for lesson_translation in translation.lessons {
+        if let Some(lesson) = course.lessons.iter_mut().find(|l| l.id == lesson_translation.lesson_id) {
+            if !lesson_translation.title.is_empty() {
+                lesson.title = lesson_translation.title;
+            }
+            // Lessons edited after translating keep their own content until
+            // the translation is updated.
+            if same_block_layout(&lesson.content, &lesson_translation.content) {
+                lesson.content = lesson_translation.content;
+            }
+        }
+    }
+    course
+}
+
+#[ic_cdk::query]
+fn get_localized_course(course_id: u64, locale: String) -> Option<Course> {
+    get_course(course_id).map(|course| public_course_view(localize_course(course, Some(&locale)), ic_cdk::caller()))
+}
+
+// Completion Certificates and Rewards
+// Formats a duration for certificates, e.g. "2h 15m".
+fn format_course_duration(secs: u64) -> String {
+    let hours = secs / 3600;
+    let minutes = (secs % 3600) / 60;
+    match (hours, minutes) {
+        (0, minutes) => format!("{}m", minutes),
+        (hours, 0) => format!("{}h", hours),
+        (hours, minutes) => format!("{}h {}m", hours, minutes),
+    }
+}
+
+fn certificate_payload(course: &Course, enrollment: &Enrollment) -> IssueCertificatePayload {
+    IssueCertificatePayload {
+        student: enrollment.student,
+        course_id: course.id,
+        course_title: course.title.clone(),
+        educator: course.educator,
+        // Profiles live in user_management; the principal stands in for the name.
+        educator_name: course.educator.to_text(),
+        metadata: CertificateMetadata {
+            completion_date: enrollment.completion_date.unwrap_or_else(time),
+            total_lessons: course.lessons.len() as u32,
+            course_duration: format_course_duration(course.duration_secs),
+            course_level: format!("{:?}", course.level),
+            skills_acquired: course.tags.clone(),
+            final_grade: enrollment.final_grade.as_ref().map(|grade| CertificateGrade {
+                percent: grade.final_percent,
+                letter: grade.letter.clone(),
+            }),
+        },
+    }
+}
+
+async fn issue_completion_certificate(enrollment: &Enrollment) -> Result<Certificate, String> {
+    let course = get_course(enrollment.course_id).ok_or_else(|| "Course not found".to_string())?;
+    issue_certificate(certificate_payload(&course, enrollment)).await
+}
+
+async fn reward_course_completion(enrollment: &Enrollment) -> Result<RewardTransaction, String> {
+    let canister = canister_config()
+        .token_rewards_canister
+        .ok_or_else(|| "Completion rewards are not configured".to_string())?;
+
+    let (result,): (Result<RewardTransaction, String>,) = ic_cdk::call(
+        canister,
+        "reward_course_completion",
+        (enrollment.student, enrollment.course_id),
+    )
+    .await
+    .map_err(|(code, msg)| format!("Completion reward failed: {:?} {}", code, msg))?;
+
+    result.map_err(|e| format!("Completion reward failed: {}", e))
+}
+
+// The courses counted towards the path, i.e. every completed step.
+fn path_certificate_payload(path: &LearningPath, event: &PathCompletionEvent) -> IssueCertificatePayload {
+    let courses: Vec<Course> = path
+        .steps
+        .iter()
+        .filter(|step| {
+            ENROLLMENT_STORAGE
+                .with(|storage| storage.borrow().get(&(event.student, step.course_id)))
+                .is_some_and(|enrollment| enrollment.completed)
+        })
+        .filter_map(|step| get_course(step.course_id))
+        .collect();
+
+    let mut skills_acquired: Vec<String> = Vec::new();
+    for tag in courses.iter().flat_map(|course| course.tags.iter()) {
+        if !skills_acquired.contains(tag) {
+            skills_acquired.push(tag.clone());
+        }
+    }
+
+    IssueCertificatePayload {
+        student: event.student,
+        course_id: path.id,
+        course_title: path.title.clone(),
+        educator: path.creator,
+        educator_name: path.creator.to_text(),
+        metadata: CertificateMetadata {
+            completion_date: event.completed_at,
+            total_lessons: courses.iter().map(|course| course.lessons.len() as u32).sum(),
+            course_duration: format_course_duration(courses.iter().map(|course| course.duration_secs).sum()),
+            course_level: "Learning path".to_string(),
+            skills_acquired,
+            final_grade: None,
+        },
+    }
+}
+
+async fn issue_certificate(payload: IssueCertificatePayload) -> Result<Certificate, String> {
+    let canister = canister_config()
+        .certificate_issuer_canister
+        .ok_or_else(|| "Certificate issuance is not configured".to_string())?;
+
+    let (result,): (Result<Certificate, String>,) = ic_cdk::call(canister, "issue_certificate", (payload,))
+        .await
+        .map_err(|(code, msg)| format!("Certificate issuance failed: {:?} {}", code, msg))?;
+
+    result.map_err(|e| format!("Certificate issuance failed: {}", e))
+}
+
+async fn reward_path_completion(event: &PathCompletionEvent) -> Result<RewardTransaction, String> {
+    let canister = canister_config()
+        .token_rewards_canister
+        .ok_or_else(|| "Completion rewards are not configured".to_string())?;
+
+    let (result,): (Result<RewardTransaction, String>,) = ic_cdk::call(
+        canister,
+        "reward_path_completion",
+        (event.student, event.path_id, event.completion_bonus),
+    )
+    .await
+    .map_err(|(code, msg)| format!("Path completion bonus failed: {:?} {}", code, msg))?;
+
+    result.map_err(|e| format!("Path completion bonus failed: {}", e))
+}
+
+fn update_path_completion_event(
+    student: Principal,
+    path_id: u64,
+    apply: impl FnOnce(&mut PathCompletionEvent),
+) {
+    PATH_COMPLETION_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        if let Some(mut event) = storage.get(&(student, path_id)) {
+            apply(&mut event);
+            storage.insert((student, path_id), event);
+        }
     });
+}
 
-    Ok(progress)
+fn queue_completion_dispatch(student: Principal, kind: CompletionKind, target_id: u64) {
+    let dispatch = CompletionDispatch {
+        student,
+        kind,
+        target_id,
+        attempts: 0,
+        last_error: None,
+        next_attempt_at: Some(time()),
+        created_at: time(),
+    };
+
+    COMPLETION_DISPATCH_STORAGE.with(|storage| {
+        storage.borrow_mut().insert((student, target_id), dispatch);
+    });
 }
 
-#[ic_cdk::update]
-fn complete_course(course_id: u64) -> Result<Enrollment, String> {
-    let caller = ic_cdk::caller();
-    
-    let enrollment_key = (caller, course_id);
+fn schedule_completion_dispatch(student: Principal, target_id: u64, delay_secs: u64) {
+    ic_cdk_timers::set_timer(Duration::from_secs(delay_secs), move || {
+        ic_cdk::spawn(dispatch_completion(student, target_id))
+    });
+}
+
+fn update_enrollment(student: Principal, course_id: u64, apply: impl FnOnce(&mut Enrollment)) {
     ENROLLMENT_STORAGE.with(|storage| {
         let mut storage = storage.borrow_mut();
-        match storage.get(&enrollment_key) {
-            Some(mut enrollment) => {
-                enrollment.completed = true;
-                enrollment.completion_date = Some(time());
-                storage.insert(enrollment_key, enrollment.clone());
-                Ok(enrollment)
-            }
-            None => Err("Not enrolled in this course".to_string()),
+        if let Some(mut enrollment) = storage.get(&(student, course_id)) {
+            apply(&mut enrollment);
+            storage.insert((student, course_id), enrollment);
         }
-    })
+    });
 }
 
-#[ic_cdk::query]
-fn get_student_enrollments(student: Principal) -> Vec<Enrollment> {
-    ENROLLMENT_STORAGE.with(|storage| {
-        storage
-            .borrow()
-            .iter()
-            .filter_map(|((s, _), enrollment)| {
-                if s == student {
-                    Some(enrollment)
-                } else {
-                    None
-                }
-            })
-            .collect()
-    })
+// Requests whatever the completed enrollment is still missing and returns
+// the errors of the calls that failed.
+async fn dispatch_course_completion(student: Principal, course_id: u64) -> Vec<String> {
+    let key = (student, course_id);
+    let enrollment = match ENROLLMENT_STORAGE.with(|storage| storage.borrow().get(&key)) {
+        Some(enrollment) if enrollment.completed => enrollment,
+        _ => return Vec::new(),
+    };
+
+    let mut errors = Vec::new();
+
+    if enrollment.certificate_id.is_none() {
+        match issue_completion_certificate(&enrollment).await {
+            Ok(certificate) => update_enrollment(student, course_id, |enrollment| {
+                enrollment.certificate_id = Some(certificate.id)
+            }),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if enrollment.reward_transaction_id.is_none() {
+        match reward_course_completion(&enrollment).await {
+            Ok(transaction) => update_enrollment(student, course_id, |enrollment| {
+                enrollment.reward_transaction_id = Some(transaction.id)
+            }),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    errors
 }
 
-#[ic_cdk::query]
-fn get_course_enrollments(course_id: u64) -> Vec<Enrollment> {
-    ENROLLMENT_STORAGE.with(|storage| {
+async fn dispatch_path_completion(student: Principal, path_id: u64) -> Vec<String> {
+    let (path, event) = match (get_learning_path(path_id), path_completion_event(student, path_id)) {
+        (Some(path), Some(event)) => (path, event),
+        _ => return Vec::new(),
+    };
+
+    let mut errors = Vec::new();
+
+    if event.certificate_id.is_none() {
+        match issue_certificate(path_certificate_payload(&path, &event)).await {
+            Ok(certificate) => update_path_completion_event(student, path_id, |event| {
+                event.certificate_id = Some(certificate.id)
+            }),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if event.bonus_transaction_id.is_none() && event.completion_bonus > 0 {
+        match reward_path_completion(&event).await {
+            Ok(transaction) => update_path_completion_event(student, path_id, |event| {
+                event.bonus_transaction_id = Some(transaction.id)
+            }),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    errors
+}
+
+// Runs a queued dispatch. The certificate issuer and token_rewards return the
+// existing record on repeat calls, so overlapping attempts cannot issue twice.
+async fn dispatch_completion(student: Principal, target_id: u64) {
+    let key = (student, target_id);
+    let kind = match COMPLETION_DISPATCH_STORAGE.with(|storage| storage.borrow().get(&key)) {
+        Some(dispatch) => dispatch.kind,
+        None => return,
+    };
+
+    let errors = match kind {
+        CompletionKind::Course => dispatch_course_completion(student, target_id).await,
+        CompletionKind::LearningPath => dispatch_path_completion(student, target_id).await,
+    };
+
+    if errors.is_empty() {
+        COMPLETION_DISPATCH_STORAGE.with(|storage| storage.borrow_mut().remove(&key));
+        return;
+    }
+
+    let mut dispatch = match COMPLETION_DISPATCH_STORAGE.with(|storage| storage.borrow().get(&key)) {
+        Some(dispatch) => dispatch,
+        None => return,
+    };
+    dispatch.attempts += 1;
+    dispatch.last_error = Some(errors.join("; "));
+    dispatch.next_attempt_at = None;
+
+    if dispatch.attempts < MAX_COMPLETION_ATTEMPTS {
+        let delay_secs = COMPLETION_RETRY_BASE_SECS << (dispatch.attempts - 1);
+        dispatch.next_attempt_at = Some(time() + delay_secs * NANOS_PER_SECOND);
+        schedule_completion_dispatch(student, target_id, delay_secs);
+    }
+
+    COMPLETION_DISPATCH_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(key, dispatch);
+    });
+}
+
+// Timers do not survive upgrades, so the reminder timer, pending retries and
+// announcement deliveries are scheduled again; reminder scans and deliveries
+// resume from their stored cursors. The enrollment indexes are filled the
+// first time this version is installed over existing data.
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    let indexes_empty = COURSE_STUDENT_INDEX.with(|index| index.borrow().is_empty())
+        && EDUCATOR_COURSE_INDEX.with(|index| index.borrow().is_empty());
+    if indexes_empty {
+        rebuild_enrollment_index_entries();
+    }
+    start_reminder_timer();
+
+    let now = time();
+    let pending: Vec<CompletionDispatch> = COMPLETION_DISPATCH_STORAGE.with(|storage| {
         storage
             .borrow()
             .iter()
-            .filter_map(|((_, c_id), enrollment)| {
-                if c_id == course_id {
-                    Some(enrollment)
+            .filter_map(|(_, dispatch)| {
+                if dispatch.next_attempt_at.is_some() {
+                    Some(dispatch)
                 } else {
                     None
                 }
             })
             .collect()
-    })
+    });
+
+    for dispatch in pending {
+        let due_at = dispatch.next_attempt_at.unwrap_or(now);
+        let delay_secs = due_at.saturating_sub(now) / NANOS_PER_SECOND;
+        schedule_completion_dispatch(dispatch.student, dispatch.target_id, delay_secs);
+    }
+
+    let deliveries: Vec<u64> = ANNOUNCEMENT_DELIVERY_STORAGE.with(|storage| {
+        storage.borrow().iter().map(|(announcement_id, _)| announcement_id).collect()
+    });
+    for announcement_id in deliveries {
+        schedule_announcement_delivery(announcement_id);
+    }
 }
 
 #[ic_cdk::query]
-fn get_lesson_progress(student: Principal, course_id: u64) -> Vec<LessonProgress> {
-    PROGRESS_STORAGE.with(|storage| {
-        storage
-            .borrow()
-            .iter()
-            .filter_map(|((s, c_id, _), progress)| {
-                if s == student && c_id == course_id {
-                    Some(progress)
-                } else {
-                    None
+fn get_completion_dispatches() -> Result<Vec<CompletionDispatch>, String> {
+    let caller = ic_cdk::caller();
+
+    if !is_admin(caller) {
+        return Err("Only controllers can view pending completion dispatches".to_string());
+    }
+
+    Ok(COMPLETION_DISPATCH_STORAGE.with(|storage| {
+        storage.borrow().iter().map(|(_, dispatch)| dispatch).collect()
+    }))
+}
+
+// Lets the learner or an admin retry immediately, including after the
+// automatic attempts have run out.
+#[ic_cdk::update]
+async fn retry_completion_dispatch(student: Principal, course_id: u64) -> Result<Enrollment, String> {
+    let caller = ic_cdk::caller();
+
+    if caller != student && !is_admin(caller) {
+        return Err("Only the learner or an admin can retry a completion".to_string());
+    }
+
+    let key = (student, course_id);
+    let enrollment = ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&key))
+        .ok_or_else(|| "Enrollment not found".to_string())?;
+
+    if !enrollment.completed {
+        return Err("Course has not been completed".to_string());
+    }
+    if enrollment.certificate_id.is_some() && enrollment.reward_transaction_id.is_some() {
+        return Ok(enrollment);
+    }
+
+    // A pending dispatch already has a timer or a call in flight. Otherwise
+    // the earlier attempts are kept so the history stays visible to admins.
+    match COMPLETION_DISPATCH_STORAGE.with(|storage| storage.borrow().get(&key)) {
+        Some(dispatch) if dispatch.next_attempt_at.is_some() => {
+            return Err("A completion retry is already pending".to_string());
+        }
+        Some(mut dispatch) => {
+            dispatch.next_attempt_at = Some(time());
+            COMPLETION_DISPATCH_STORAGE.with(|storage| {
+                storage.borrow_mut().insert(key, dispatch);
+            });
+        }
+        None => queue_completion_dispatch(student, CompletionKind::Course, course_id),
+    }
+    dispatch_completion(student, course_id).await;
+
+    ENROLLMENT_STORAGE
+        .with(|storage| storage.borrow().get(&key))
+        .ok_or_else(|| "Enrollment not found".to_string())
+}
+
+// Benchmarks, run with `canbench` from src/course_management. Each benchmark
+// runs on a fresh canister seeded with the same data.
+#[cfg(feature = "canbench-rs")]
+mod benches {
+    use super::*;
+    use canbench_rs::{bench, bench_fn, BenchResult};
+    use std::hint::black_box;
+
+    const BENCH_EDUCATORS: u64 = 10;
+    const BENCH_COURSES: u64 = 50;
+    const BENCH_STUDENTS: u64 = 1_000;
+    const BENCH_COURSES_PER_STUDENT: u64 = 5;
+    const BENCH_LESSONS: u64 = 4;
+
+    fn educator(index: u64) -> Principal {
+        Principal::from_slice(&(1_000_000 + index).to_be_bytes())
+    }
+
+    fn student(index: u64) -> Principal {
+        Principal::from_slice(&index.to_be_bytes())
+    }
+
+    // The full scans the enrollment indexes replaced.
+    fn scan_course_enrollments(course_id: u64) -> Vec<Enrollment> {
+        ENROLLMENT_STORAGE.with(|storage| {
+            storage
+                .borrow()
+                .iter()
+                .filter_map(|((_, c_id), enrollment)| {
+                    if c_id == course_id {
+                        Some(enrollment)
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+    }
+
+    fn scan_educator_courses(educator: Principal) -> Vec<Course> {
+        COURSE_STORAGE.with(|storage| {
+            storage
+                .borrow()
+                .iter()
+                .filter_map(|(_, course)| {
+                    if course.educator == educator {
+                        Some(course)
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+    }
+
+    fn scan_lesson_progress(student: Principal, course_id: u64) -> Vec<LessonProgress> {
+        PROGRESS_STORAGE.with(|storage| {
+            storage
+                .borrow()
+                .iter()
+                .filter_map(|((s, c_id, _), progress)| {
+                    if s == student && c_id == course_id {
+                        Some(progress)
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+    }
+
+    // 50 courses, 5,000 enrollments and 20,000 progress rows. Only the
+    // primary stores are written; the indexes are filled the way an upgrade
+    // over existing data fills them.
+    fn seed() {
+        for course_id in 1..=BENCH_COURSES {
+            let lessons: Vec<Lesson> = (1..=BENCH_LESSONS)
+                .map(|lesson_id| Lesson {
+                    id: course_id * 100 + lesson_id,
+                    title: format!("Lesson {}", lesson_id),
+                    content: Vec::new(),
+                    duration_secs: 600,
+                    order: lesson_id as u32,
+                    deadline: None,
+                    unlock: None,
+                    branches: Vec::new(),
+                })
+                .collect();
+            let course = Course {
+                id: course_id,
+                title: format!("Course {}", course_id),
+                description: "Benchmark course".to_string(),
+                educator: educator(course_id % BENCH_EDUCATORS),
+                duration_secs: lessons.iter().map(|lesson| lesson.duration_secs).sum(),
+                lessons,
+                level: CourseLevel::Beginner,
+                image: String::new(),
+                tags: Vec::new(),
+                prerequisites: Vec::new(),
+                published: true,
+                enrollment_policy: EnrollmentPolicy::Open,
+                price: 0,
+                reviews_require_completion: false,
+                default_locale: DEFAULT_LOCALE.to_string(),
+                rating_total: 0,
+                rating_count: 0,
+                average_rating: 0.0,
+                enrolled_students: 0,
+                created_at: 0,
+                updated_at: 0,
+            };
+            COURSE_STORAGE.with(|storage| {
+                storage.borrow_mut().insert(course_id, course);
+            });
+        }
+
+        for index in 0..BENCH_STUDENTS {
+            for offset in 0..BENCH_COURSES_PER_STUDENT {
+                let course_id = (index + offset * BENCH_EDUCATORS) % BENCH_COURSES + 1;
+                let enrollment = Enrollment {
+                    student: student(index),
+                    course_id,
+                    enrolled_at: 0,
+                    cohort_id: None,
+                    purchase_id: None,
+                    completed: false,
+                    completion_date: None,
+                    final_grade: None,
+                    certificate_id: None,
+                    reward_transaction_id: None,
+                };
+                ENROLLMENT_STORAGE.with(|storage| {
+                    storage.borrow_mut().insert((student(index), course_id), enrollment);
+                });
+                for lesson_id in 1..=BENCH_LESSONS {
+                    let progress = LessonProgress {
+                        student: student(index),
+                        course_id,
+                        lesson_id: course_id * 100 + lesson_id,
+                        completed: true,
+                        completed_at: Some(0),
+                        late: false,
+                    };
+                    PROGRESS_STORAGE.with(|storage| {
+                        storage
+                            .borrow_mut()
+                            .insert((student(index), course_id, progress.lesson_id), progress);
+                    });
                 }
-            })
-            .collect()
-    })
+            }
+        }
+
+        rebuild_enrollment_index_entries();
+    }
+
+    #[bench(raw)]
+    fn course_enrollments_full_scan() -> BenchResult {
+        seed();
+        bench_fn(|| black_box(scan_course_enrollments(1)))
+    }
+
+    #[bench(raw)]
+    fn course_enrollments_indexed() -> BenchResult {
+        seed();
+        bench_fn(|| black_box(get_course_enrollments(1)))
+    }
+
+    #[bench(raw)]
+    fn educator_courses_full_scan() -> BenchResult {
+        seed();
+        bench_fn(|| black_box(scan_educator_courses(educator(1))))
+    }
+
+    #[bench(raw)]
+    fn educator_courses_indexed() -> BenchResult {
+        seed();
+        bench_fn(|| black_box(get_courses_by_educator(educator(1))))
+    }
+
+    #[bench(raw)]
+    fn lesson_progress_full_scan() -> BenchResult {
+        seed();
+        bench_fn(|| black_box(scan_lesson_progress(student(0), 1)))
+    }
+
+    #[bench(raw)]
+    fn lesson_progress_indexed() -> BenchResult {
+        seed();
+        bench_fn(|| black_box(get_lesson_progress(student(0), 1)))
+    }
 }
 
 // Export Candid interface
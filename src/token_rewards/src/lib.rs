@@ -11,6 +11,8 @@ type IdStore = StableBTreeMap<u8, u64, Memory>;
 type BalanceStore = StableBTreeMap<Principal, u64, Memory>;
 type TransactionStore = StableBTreeMap<u64, Transaction, Memory>;
 type UserTransactionStore = StableBTreeMap<Principal, Vec<u64>, Memory>;
+type PlatformConfigStore = StableBTreeMap<u8, PlatformConfig, Memory>;
+type PurchaseStore = StableBTreeMap<u64, Purchase, Memory>;
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub enum TransactionType {
@@ -20,6 +22,13 @@ pub enum TransactionType {
     QuestionAnswer,
     StudyGroupParticipation,
     Bonus,
+    // Course sales. `CoursePurchase` and `SaleReversal` debit the user; every
+    // other transaction type is a credit.
+    CoursePurchase,
+    CourseSale,
+    PlatformFee,
+    PurchaseRefund,
+    SaleReversal,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
@@ -42,6 +51,48 @@ pub struct RewardPayload {
     pub related_id: Option<u64>,
 }
 
+// `trusted_canisters` may move balances on behalf of users, e.g. the course
+// canister charging for paid enrollments. `max_path_bonus` caps the learning
+// path completion bonus a trusted canister can pay out.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PlatformConfig {
+    pub treasury: Principal,
+    pub platform_fee_percent: u32,
+    pub trusted_canisters: Vec<Principal>,
+    pub max_path_bonus: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PlatformConfigPayload {
+    pub treasury: Principal,
+    pub platform_fee_percent: u32,
+    pub trusted_canisters: Vec<Principal>,
+    pub max_path_bonus: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct Purchase {
+    pub id: u64,
+    pub merchant: Principal, // canister that charged the buyer
+    pub buyer: Principal,
+    pub seller: Principal,
+    pub course_id: u64,
+    pub price: u64,
+    pub platform_fee: u64,
+    pub treasury: Principal, // account the platform fee was paid to
+    pub purchased_at: u64,
+    pub refunded_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PurchasePayload {
+    pub buyer: Principal,
+    pub seller: Principal,
+    pub course_id: u64,
+    pub price: u64,
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct LeaderboardEntry {
     pub user: Principal,
@@ -77,6 +128,18 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
         )
     );
+
+    static PLATFORM_CONFIG: RefCell<PlatformConfigStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+        )
+    );
+
+    static PURCHASE_STORAGE: RefCell<PurchaseStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+        )
+    );
 }
 
 fn get_next_id() -> u64 {
@@ -88,32 +151,61 @@ fn get_next_id() -> u64 {
     })
 }
 
+// Rewards mint new tokens, so only trusted canisters may pay them out.
 #[ic_cdk::update]
 fn reward_user(payload: RewardPayload) -> Result<Transaction, String> {
-    let caller = ic_cdk::caller();
-    
-    if caller == Principal::anonymous() {
-        return Err("Anonymous users cannot reward tokens".to_string());
-    }
+    ensure_trusted_canister(ic_cdk::caller())?;
+
+    // Update user balance
+    credit(payload.user, payload.amount);
+
+    Ok(record_transaction(
+        payload.user,
+        payload.transaction_type,
+        payload.amount,
+        payload.description,
+        payload.related_id,
+    ))
+}
+
+fn credit(user: Principal, amount: u64) {
+    BALANCE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        let current_balance = storage.get(&user).unwrap_or(0);
+        storage.insert(user, current_balance + amount);
+    });
+}
+
+fn debit(user: Principal, amount: u64) -> Result<(), String> {
+    BALANCE_STORAGE.with(|storage| {
+        let mut storage = storage.borrow_mut();
+        let current_balance = storage.get(&user).unwrap_or(0);
+        if current_balance < amount {
+            return Err("Insufficient token balance".to_string());
+        }
+        storage.insert(user, current_balance - amount);
+        Ok(())
+    })
+}
 
+fn record_transaction(
+    user: Principal,
+    transaction_type: TransactionType,
+    amount: u64,
+    description: String,
+    related_id: Option<u64>,
+) -> Transaction {
     let transaction_id = get_next_id();
     let transaction = Transaction {
         id: transaction_id,
-        user: payload.user,
-        transaction_type: payload.transaction_type,
-        amount: payload.amount,
-        description: payload.description,
+        user,
+        transaction_type,
+        amount,
+        description,
         timestamp: time(),
-        related_id: payload.related_id,
+        related_id,
     };
 
-    // Update user balance
-    BALANCE_STORAGE.with(|storage| {
-        let mut storage = storage.borrow_mut();
-        let current_balance = storage.get(&payload.user).unwrap_or(0);
-        storage.insert(payload.user, current_balance + payload.amount);
-    });
-
     // Store transaction
     TRANSACTION_STORAGE.with(|storage| {
         storage.borrow_mut().insert(transaction_id, transaction.clone());
@@ -122,12 +214,12 @@ fn reward_user(payload: RewardPayload) -> Result<Transaction, String> {
     // Add to user's transaction history
     USER_TRANSACTIONS.with(|storage| {
         let mut storage = storage.borrow_mut();
-        let mut transactions = storage.get(&payload.user).unwrap_or_default();
+        let mut transactions = storage.get(&user).unwrap_or_default();
         transactions.push(transaction_id);
-        storage.insert(payload.user, transactions);
+        storage.insert(user, transactions);
     });
 
-    Ok(transaction)
+    transaction
 }
 
 #[ic_cdk::query]
@@ -199,8 +291,20 @@ fn get_transaction_history(limit: u32) -> Vec<Transaction> {
 }
 
 // Predefined reward amounts for different actions
+// A course is only rewarded once per user; repeat calls return the original
+// transaction so course_management can retry safely.
 #[ic_cdk::update]
 fn reward_course_completion(user: Principal, course_id: u64) -> Result<Transaction, String> {
+    ensure_trusted_canister(ic_cdk::caller())?;
+
+    let existing = get_user_transactions(user).into_iter().find(|transaction| {
+        matches!(transaction.transaction_type, TransactionType::CourseCompletion)
+            && transaction.related_id == Some(course_id)
+    });
+    if let Some(transaction) = existing {
+        return Ok(transaction);
+    }
+
     reward_user(RewardPayload {
         user,
         transaction_type: TransactionType::CourseCompletion,
@@ -210,6 +314,36 @@ fn reward_course_completion(user: Principal, course_id: u64) -> Result<Transacti
     })
 }
 
+// Pays a learning path's completion bonus once per user; the amount is set
+// on the path in course_management and may not exceed `max_path_bonus`.
+#[ic_cdk::update]
+fn reward_path_completion(user: Principal, path_id: u64, amount: u64) -> Result<Transaction, String> {
+    let config = ensure_trusted_canister(ic_cdk::caller())?;
+
+    if amount > config.max_path_bonus {
+        return Err(format!(
+            "Path completion bonus exceeds the platform maximum of {}",
+            config.max_path_bonus
+        ));
+    }
+
+    let existing = get_user_transactions(user).into_iter().find(|transaction| {
+        matches!(transaction.transaction_type, TransactionType::Bonus)
+            && transaction.related_id == Some(path_id)
+    });
+    if let Some(transaction) = existing {
+        return Ok(transaction);
+    }
+
+    reward_user(RewardPayload {
+        user,
+        transaction_type: TransactionType::Bonus,
+        amount,
+        description: "Learning path completion bonus".to_string(),
+        related_id: Some(path_id),
+    })
+}
+
 #[ic_cdk::update]
 fn reward_lesson_completion(user: Principal, course_id: u64, lesson_id: u64) -> Result<Transaction, String> {
     reward_user(RewardPayload {
@@ -232,5 +366,195 @@ fn reward_community_help(user: Principal, question_id: u64) -> Result<Transactio
     })
 }
 
+// Course Purchases
+fn platform_config() -> Option<PlatformConfig> {
+    PLATFORM_CONFIG.with(|storage| storage.borrow().get(&0))
+}
+
+fn ensure_trusted_canister(caller: Principal) -> Result<PlatformConfig, String> {
+    match platform_config() {
+        Some(config) if config.trusted_canisters.contains(&caller) => Ok(config),
+        _ => Err("Only trusted canisters can move user balances".to_string()),
+    }
+}
+
+#[ic_cdk::update]
+fn set_platform_config(payload: PlatformConfigPayload) -> Result<PlatformConfig, String> {
+    let caller = ic_cdk::caller();
+
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers can configure the platform".to_string());
+    }
+    if payload.platform_fee_percent > 100 {
+        return Err("Platform fee must be between 0 and 100 percent".to_string());
+    }
+
+    let config = PlatformConfig {
+        treasury: payload.treasury,
+        platform_fee_percent: payload.platform_fee_percent,
+        trusted_canisters: payload.trusted_canisters,
+        max_path_bonus: payload.max_path_bonus,
+        updated_at: time(),
+    };
+
+    PLATFORM_CONFIG.with(|storage| {
+        storage.borrow_mut().insert(0, config.clone());
+    });
+
+    Ok(config)
+}
+
+#[ic_cdk::query]
+fn get_platform_config() -> Option<PlatformConfig> {
+    platform_config()
+}
+
+// Charges the buyer and splits the price between the seller and the
+// platform treasury. Only trusted canisters may call this.
+#[ic_cdk::update]
+fn purchase_course(payload: PurchasePayload) -> Result<Purchase, String> {
+    let caller = ic_cdk::caller();
+    let config = ensure_trusted_canister(caller)?;
+
+    if payload.price == 0 {
+        return Err("Price must be greater than zero".to_string());
+    }
+    if payload.buyer == payload.seller {
+        return Err("Buyer and seller must differ".to_string());
+    }
+
+    let platform_fee = payload.price * config.platform_fee_percent as u64 / 100;
+    debit(payload.buyer, payload.price)?;
+    credit(payload.seller, payload.price - platform_fee);
+    credit(config.treasury, platform_fee);
+
+    let purchase_id = get_next_id();
+    let purchase = Purchase {
+        id: purchase_id,
+        merchant: caller,
+        buyer: payload.buyer,
+        seller: payload.seller,
+        course_id: payload.course_id,
+        price: payload.price,
+        platform_fee,
+        treasury: config.treasury,
+        purchased_at: time(),
+        refunded_at: None,
+    };
+
+    record_transaction(
+        payload.buyer,
+        TransactionType::CoursePurchase,
+        payload.price,
+        "Course purchase".to_string(),
+        Some(payload.course_id),
+    );
+    record_transaction(
+        payload.seller,
+        TransactionType::CourseSale,
+        payload.price - platform_fee,
+        "Course sale".to_string(),
+        Some(payload.course_id),
+    );
+    if platform_fee > 0 {
+        record_transaction(
+            config.treasury,
+            TransactionType::PlatformFee,
+            platform_fee,
+            "Platform fee on course sale".to_string(),
+            Some(payload.course_id),
+        );
+    }
+
+    PURCHASE_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(purchase_id, purchase.clone());
+    });
+
+    Ok(purchase)
+}
+
+// Returns the full price to the buyer, reversing the seller's share and the
+// platform fee. The fee is taken back from the treasury that received it,
+// even if the platform treasury has changed since. Only the canister that
+// made the purchase can refund it.
+#[ic_cdk::update]
+fn refund_purchase(purchase_id: u64) -> Result<Purchase, String> {
+    let caller = ic_cdk::caller();
+    ensure_trusted_canister(caller)?;
+
+    let mut purchase = PURCHASE_STORAGE
+        .with(|storage| storage.borrow().get(&purchase_id))
+        .ok_or_else(|| "Purchase not found".to_string())?;
+
+    if purchase.merchant != caller {
+        return Err("Only the charging canister can refund this purchase".to_string());
+    }
+    if purchase.refunded_at.is_some() {
+        return Err("Purchase has already been refunded".to_string());
+    }
+
+    let seller_share = purchase.price - purchase.platform_fee;
+    debit(purchase.seller, seller_share)
+        .map_err(|_| "Seller balance is insufficient for the refund".to_string())?;
+    if let Err(e) = debit(purchase.treasury, purchase.platform_fee) {
+        credit(purchase.seller, seller_share);
+        return Err(e);
+    }
+    credit(purchase.buyer, purchase.price);
+
+    record_transaction(
+        purchase.seller,
+        TransactionType::SaleReversal,
+        seller_share,
+        "Course sale refunded".to_string(),
+        Some(purchase.course_id),
+    );
+    if purchase.platform_fee > 0 {
+        record_transaction(
+            purchase.treasury,
+            TransactionType::SaleReversal,
+            purchase.platform_fee,
+            "Platform fee refunded".to_string(),
+            Some(purchase.course_id),
+        );
+    }
+    record_transaction(
+        purchase.buyer,
+        TransactionType::PurchaseRefund,
+        purchase.price,
+        "Course purchase refunded".to_string(),
+        Some(purchase.course_id),
+    );
+
+    purchase.refunded_at = Some(time());
+    PURCHASE_STORAGE.with(|storage| {
+        storage.borrow_mut().insert(purchase_id, purchase.clone());
+    });
+
+    Ok(purchase)
+}
+
+#[ic_cdk::query]
+fn get_purchase(purchase_id: u64) -> Option<Purchase> {
+    PURCHASE_STORAGE.with(|storage| storage.borrow().get(&purchase_id))
+}
+
+#[ic_cdk::query]
+fn get_user_purchases(user: Principal) -> Vec<Purchase> {
+    PURCHASE_STORAGE.with(|storage| {
+        storage
+            .borrow()
+            .iter()
+            .filter_map(|(_, purchase)| {
+                if purchase.buyer == user {
+                    Some(purchase)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    })
+}
+
 // Export Candid interface
 ic_cdk::export_candid!();
\ No newline at end of file